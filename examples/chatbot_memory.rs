@@ -1,7 +1,7 @@
-use memory_graph::models::{Memory, MemoryType};
+use memory_graph::models::{DEFAULT_SLOT, Memory, MemoryType};
 use memory_graph::storage::StorageManager;
-use memory_graph::index::{VectorIndex, vector::SimpleVectorIndex};
-use memory_graph::query::{Query, Search, VectorSearch, Traverse, engine::QueryEngine};
+use memory_graph::index::{VectorIndex, persist, vector::SimpleVectorIndex};
+use memory_graph::query::{Query, Search, VectorSearch, Traverse, Direction, engine::QueryEngine};
 use anyhow::Result;
 use std::collections::HashMap;
 use serde_json::json;
@@ -10,9 +10,9 @@ fn main() -> Result<()> {
     println!("--- AI Chatbot Memory Example ---");
 
     // 1. Initialize Engine
-    // In a real app, you'd persist this to disk.
+    // Memories from earlier sessions are picked up from the index snapshot.
     let storage = StorageManager::new("chatbot_memory.db")?;
-    let mut vector_index = SimpleVectorIndex::new();
+    let mut vector_index = persist::load_or_rebuild(&storage, DEFAULT_SLOT, SimpleVectorIndex::new)?;
 
     // 2. Simulate a Conversation
     println!("Simulating conversation...");
@@ -60,6 +60,9 @@ fn main() -> Result<()> {
     )?;
     println!("Stored conversation and extracted fact.");
 
+    // Index now matches storage again; stamp the snapshot for the next session.
+    persist::save(&storage, DEFAULT_SLOT, &vector_index)?;
+
     // Turn 3: User asks for a recommendation later
     // "What should I do this weekend?"
     // Embedding: [0.2, 0.7, 0.3] (Simulated "activity/weekend" vector, close to hiking)
@@ -71,17 +74,26 @@ fn main() -> Result<()> {
     let query = Query {
         filter: None, // Could filter by user_id here
         search: Some(Search {
-            vector: VectorSearch {
+            vector: Some(VectorSearch {
                 text: Some("weekend activity recommendation".to_string()),
                 embedding: Some(vec![0.2, 0.7, 0.3]), 
                 threshold: Some(0.8), // High threshold for relevance
-            }
+                radius: false,
+                slot: None,
+            }),
+            text: None,
+            fusion: None,
         }),
         // We also want to traverse to see *why* we know this (the source conversation)
         traverse: Some(Traverse {
-            direction: "outbound".to_string(), // Look for what this fact is connected to
+            direction: Direction::Outbound, // Look for what this fact is connected to
             edge_types: Some(vec!["derived_from".to_string()]),
+            exclude_edge_types: None,
+            edge_weights: None,
             depth: Some(1),
+            attenuation: None,
+            max_nodes: None,
+            mode: None,
         }),
         rank_by: None,
         limit: Some(5),
@@ -94,7 +106,7 @@ fn main() -> Result<()> {
     if results.is_empty() {
         println!("No relevant memories found.");
     } else {
-        for (i, mem) in results.iter().enumerate() {
+        for (i, mem) in results.iter().map(|result| &result.memory).enumerate() {
             match &mem.memory_type {
                 MemoryType::Semantic { confidence, .. } => {
                     println!("{}. [FACT] {} (Confidence: {})", i+1, mem.content, confidence);
@@ -107,7 +119,7 @@ fn main() -> Result<()> {
         }
         
         println!("\nBot Response Generation:");
-        println!("Based on the fact '{}', I suggest you go for a hike!", results[0].content);
+        println!("Based on the fact '{}', I suggest you go for a hike!", results[0].memory.content);
     }
 
     Ok(())
@@ -1,18 +1,18 @@
-use memory_graph::models::Memory;
+use memory_graph::models::{DEFAULT_SLOT, Memory};
 use memory_graph::storage::StorageManager;
-use memory_graph::index::{VectorIndex, vector::SimpleVectorIndex};
-use memory_graph::query::{Query, Search, VectorSearch, Traverse, engine::QueryEngine};
+use memory_graph::index::{adjacency::AdjacencyCache, persist, vector::SimpleVectorIndex};
+use memory_graph::graph::{self, PathOptions};
+use memory_graph::query::{Query, Search, TextSearch, VectorSearch, Traverse, Direction, engine::QueryEngine};
 use anyhow::Result;
 use std::fs::File;
 use std::io::BufReader;
-use uuid::Uuid;
+use std::sync::Arc;
 
 fn main() -> Result<()> {
     println!("--- Mars Colony AI Simulation ---");
 
     // 1. Initialize Engine
-    let storage = StorageManager::new("mars_colony.db")?;
-    let mut vector_index = SimpleVectorIndex::new();
+    let storage = Arc::new(StorageManager::new("mars_colony.db")?);
 
     // 2. Load Data
     println!("Loading dataset from data/mars_colony.json...");
@@ -23,26 +23,30 @@ fn main() -> Result<()> {
     // 3. Ingest Data
     for mem in &memories {
         storage.save_memory(mem)?;
-        vector_index.add(mem.id, &mem.embedding)?;
     }
     println!("Ingested {} memories.", memories.len());
 
+    // The index is derived from storage, so it is opened after ingestion.
+    let vector_index = persist::load_or_rebuild(&storage, DEFAULT_SLOT, SimpleVectorIndex::new)?;
+    // Edges are written through the cache, so traversals read adjacency from memory.
+    let adjacency = AdjacencyCache::load(storage.clone())?;
+
     // 4. Create Edges (Simulating Knowledge Graph Construction)
     // Edge 1: "Sensor anomaly" (0) -> "Sector 7 houses fusion conduit" (1)
     // Relation: "located_at" / "involves"
     let id_anomaly = memories[0].id;
     let id_sector_info = memories[1].id;
-    storage.add_edge_inherent(id_anomaly, id_sector_info, "related_context".to_string(), 0.9)?;
+    adjacency.add_edge(id_anomaly, id_sector_info, "related_context".to_string(), 0.9)?;
 
     // Edge 2: "Sector 7 houses fusion conduit" (1) -> "Fusion conduits emit thermal spikes..." (2)
     // Relation: "explained_by"
     let id_physics = memories[2].id;
-    storage.add_edge_inherent(id_sector_info, id_physics, "physical_principle".to_string(), 0.85)?;
+    adjacency.add_edge(id_sector_info, id_physics, "physical_principle".to_string(), 0.85)?;
 
     // Edge 3: "Fusion conduits emit thermal spikes..." (2) -> "Commander Lewis authorized coolant flush" (3)
     // Relation: "caused_by_action" (Coolant flush -> Low pressure -> Thermal spikes)
     let id_action = memories[3].id;
-    storage.add_edge_inherent(id_physics, id_action, "potential_cause".to_string(), 0.7)?;
+    adjacency.add_edge(id_physics, id_action, "potential_cause".to_string(), 0.7)?;
 
     println!("Knowledge Graph constructed.");
 
@@ -51,31 +55,46 @@ fn main() -> Result<()> {
     // Scenario A: "Why is there a thermal spike?"
     // The agent sees "thermal spike" (vector match) and needs to find the root cause (graph traversal).
     println!("\n--- Scenario A: Root Cause Analysis ---");
-    println!("Query: 'thermal spike' + 2-hop traversal");
+    println!("Query: 'thermal spike' + 3-hop traversal");
     
     let query_a = Query {
         filter: None,
         search: Some(Search {
-            vector: VectorSearch {
+            vector: Some(VectorSearch {
                 text: Some("thermal spike".to_string()),
                 embedding: Some(vec![0.8, 0.1, 0.1]), // Matches anomaly
-                threshold: None,
-            }
+                threshold: Some(0.99), // Seed on the anomaly alone
+                radius: false,
+                slot: None,
+            }),
+            text: None,
+            fusion: None,
         }),
         traverse: Some(Traverse {
-            direction: "outbound".to_string(),
+            direction: Direction::Outbound,
             edge_types: None,
-            depth: Some(2), // Deep traversal to find the Commander's action
+            exclude_edge_types: None,
+            edge_weights: None,
+            depth: Some(3), // Anomaly -> Sector 7 -> Physics -> Commander's action
+            attenuation: None,
+            max_nodes: None,
+            mode: None,
         }),
         rank_by: None,
         limit: Some(10),
     };
 
-    let engine = QueryEngine::new(&storage, &vector_index);
+    let engine = QueryEngine::new(&storage, &vector_index).with_graph(&adjacency);
     let results_a = engine.execute(query_a)?;
 
-    for (i, mem) in results_a.iter().enumerate() {
-        println!("{}. [{}] {} (Score: High)", i+1, mem.id, mem.content);
+    for (i, result) in results_a.iter().enumerate() {
+        println!("{}. [{}] {} (Score: {:.3})", i+1, result.memory.id, result.memory.content, result.score);
+        // Explain how traversal reached it, e.g. for an LLM prompt.
+        if let Some(step) = result.path.last()
+            && let Some(parent) = storage.get_memory(step.from)?
+        {
+            println!("   reached via {} from \"{}\" ({} hops)", step.relation_type, parent.content, result.hops);
+        }
     }
 
     // Scenario B: "What did Commander Lewis do?"
@@ -86,24 +105,54 @@ fn main() -> Result<()> {
     let query_b = Query {
         filter: None,
         search: Some(Search {
-            vector: VectorSearch {
+            vector: Some(VectorSearch {
                 text: Some("Commander Lewis".to_string()),
                 embedding: Some(vec![0.1, 0.1, 0.9]), // Matches action
                 threshold: None,
-            }
+                radius: false,
+                slot: None,
+            }),
+            // The name is a keyword match, so fuse it with the vector ranking.
+            text: Some(TextSearch { query: "Commander Lewis".to_string() }),
+            fusion: None,
         }),
         traverse: Some(Traverse {
-            direction: "inbound".to_string(), // Look backwards: What points to this?
+            direction: Direction::Inbound, // Look backwards: What points to this?
             edge_types: None,
-            depth: Some(2),
+            exclude_edge_types: None,
+            edge_weights: None,
+            depth: Some(3),
+            attenuation: None,
+            max_nodes: None,
+            mode: None,
         }),
         rank_by: None,
         limit: Some(10),
     };
 
     let results_b = engine.execute(query_b)?;
-    for (i, mem) in results_b.iter().enumerate() {
-        println!("{}. [{}] {}", i+1, mem.id, mem.content);
+    for (i, result) in results_b.iter().enumerate() {
+        println!("{}. [{}] {} ({:?}, {} hops)", i+1, result.memory.id, result.memory.content, result.stage, result.hops);
+    }
+
+    // Scenario C: "How is the sensor anomaly connected to the commander?"
+    println!("\n--- Scenario C: Connection Explanation ---");
+    println!("Query: shortest path from the anomaly to Commander Lewis' action");
+
+    let options = PathOptions { direction: Direction::Both, ..Default::default() };
+    match graph::shortest_path(&adjacency, id_anomaly, id_action, &options)? {
+        Some(path) => {
+            for step in &path.steps {
+                let from = storage.get_memory(step.from)?.map(|m| m.content).unwrap_or_default();
+                let to = storage.get_memory(step.to)?.map(|m| m.content).unwrap_or_default();
+                match step.direction {
+                    Direction::Inbound => println!("\"{}\" <--{}-- \"{}\"", from, step.relation_type, to),
+                    _ => println!("\"{}\" --{}--> \"{}\"", from, step.relation_type, to),
+                }
+            }
+            println!("({} hops, cost {:.2})", path.steps.len(), path.cost);
+        }
+        None => println!("No connection found."),
     }
 
     Ok(())
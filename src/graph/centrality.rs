@@ -0,0 +1,191 @@
+//! PageRank and degree centrality over the whole memory graph.
+//!
+//! Computed in memory from one snapshot and stored per memory in redb, so
+//! ranking reads one row per result instead of walking the graph. Stored
+//! values carry the `graph_revision` they were computed at; `refresh` only
+//! recomputes once the graph has changed.
+
+use super::EdgeSource;
+use crate::storage::{StorageManager, StorageReader};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Centrality {
+    /// Weighted PageRank; sums to 1 over all memories
+    pub pagerank: f32,
+    /// `pagerank` divided by the highest PageRank in the graph, in (0, 1]
+    pub relative_pagerank: f32,
+    pub in_degree: u32,
+    pub out_degree: u32,
+    /// `(in_degree + out_degree) / (n - 1)` for a graph of `n` memories
+    pub degree: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PageRankConfig {
+    /// Probability of following an edge rather than jumping to a random memory.
+    pub damping: f32,
+    pub max_iterations: usize,
+    /// Stop once the total change in rank over one iteration is below this.
+    pub tolerance: f32,
+}
+
+impl Default for PageRankConfig {
+    fn default() -> Self {
+        Self {
+            damping: 0.85,
+            max_iterations: 100,
+            tolerance: 1e-6,
+        }
+    }
+}
+
+/// Centrality of every memory in `reader`'s snapshot, over the edges in
+/// `graph` (the snapshot itself, or an `AdjacencyCache`).
+///
+/// An edge passes rank to its target in proportion to `Edge.weight`; edges
+/// with a non-positive weight, or to memories that no longer exist, count
+/// towards degree only. Memories without usable outbound edges spread their
+/// rank evenly over the graph. Power iteration starts from `warm` where it
+/// has a value, which converges in a few iterations after small changes.
+pub fn compute<G: EdgeSource + ?Sized>(
+    reader: &StorageReader,
+    graph: &G,
+    config: &PageRankConfig,
+    warm: Option<&HashMap<Uuid, Centrality>>,
+) -> Result<HashMap<Uuid, Centrality>> {
+    let ids = reader.memory_ids()?;
+    let n = ids.len();
+    if n == 0 {
+        return Ok(HashMap::new());
+    }
+    let position: HashMap<Uuid, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+    let mut in_degree = vec![0u32; n];
+    let mut out_degree = vec![0u32; n];
+    let mut links: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+    for (source, edges) in graph.all_edges_out()? {
+        let Some(&from) = position.get(&source) else {
+            continue;
+        };
+        out_degree[from] += edges.len() as u32;
+        for edge in edges {
+            let Some(&to) = position.get(&edge.target_id) else {
+                continue;
+            };
+            in_degree[to] += 1;
+            if edge.weight > 0.0 {
+                links[from].push((to, edge.weight as f64));
+            }
+        }
+    }
+    for row in &mut links {
+        let total: f64 = row.iter().map(|(_, weight)| weight).sum();
+        for (_, weight) in row.iter_mut() {
+            *weight /= total;
+        }
+    }
+
+    let uniform = 1.0 / n as f64;
+    let mut rank: Vec<f64> = ids.iter()
+        .map(|id| warm.and_then(|warm| warm.get(id)).map_or(uniform, |c| c.pagerank as f64))
+        .collect();
+    let total: f64 = rank.iter().sum();
+    rank.iter_mut().for_each(|r| *r /= total);
+
+    let damping = config.damping as f64;
+    for _ in 0..config.max_iterations {
+        let dangling: f64 = (0..n).filter(|&i| links[i].is_empty()).map(|i| rank[i]).sum();
+        let base = (1.0 - damping + damping * dangling) * uniform;
+        let mut next = vec![base; n];
+        for (from, row) in links.iter().enumerate() {
+            for &(to, share) in row {
+                next[to] += damping * rank[from] * share;
+            }
+        }
+        let change: f64 = next.iter().zip(&rank).map(|(a, b)| (a - b).abs()).sum();
+        rank = next;
+        if change < config.tolerance as f64 {
+            break;
+        }
+    }
+
+    let max_rank = rank.iter().copied().fold(0.0, f64::max);
+    let others = (n - 1).max(1) as f32;
+    Ok(ids.into_iter().enumerate().map(|(i, id)| {
+        (id, Centrality {
+            pagerank: rank[i] as f32,
+            relative_pagerank: (rank[i] / max_rank) as f32,
+            in_degree: in_degree[i],
+            out_degree: out_degree[i],
+            degree: (in_degree[i] + out_degree[i]) as f32 / others,
+        })
+    }).collect())
+}
+
+/// Recomputes and stores centrality if the graph has changed since it was
+/// last computed, warm-starting from the stored values. Returns whether it
+/// recomputed. `graph` supplies the edges, as in `compute`.
+pub fn refresh<G: EdgeSource + ?Sized>(storage: &StorageManager, graph: &G, config: &PageRankConfig) -> Result<bool> {
+    let reader = storage.read()?;
+    let revision = reader.graph_revision()?;
+    if storage.centrality_revision()? == Some(revision) {
+        return Ok(false);
+    }
+    let previous = reader.list_centrality()?;
+    let scores = compute(&reader, graph, config, Some(&previous))?;
+    storage.save_centrality(&scores, revision)?;
+    Ok(true)
+}
+
+/// Recomputes and stores centrality from scratch over the edges in `graph`.
+pub fn recompute<G: EdgeSource + ?Sized>(storage: &StorageManager, graph: &G, config: &PageRankConfig) -> Result<()> {
+    let reader = storage.read()?;
+    let scores = compute(&reader, graph, config, None)?;
+    storage.save_centrality(&scores, reader.graph_revision()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::testing::unembedded;
+
+    #[test]
+    fn pagerank_favours_memories_many_others_point_at() {
+        // Four memories cite a hub, which cites back to memory 1; memory 5 is
+        // isolated.
+        let (storage, ids) = unembedded(6, &[
+            (1, 0, "cites", 1.0),
+            (2, 0, "cites", 1.0),
+            (3, 0, "cites", 1.0),
+            (4, 0, "cites", 1.0),
+            (0, 1, "cites", 1.0),
+        ]);
+        let config = PageRankConfig::default();
+        let reader = storage.read().unwrap();
+        let scores = compute(&reader, &reader, &config, None).unwrap();
+
+        let total: f32 = scores.values().map(|c| c.pagerank).sum();
+        assert!((total - 1.0).abs() < 1e-4, "pagerank sums to {total}");
+        let hub = &scores[&ids[0]];
+        assert_eq!((hub.in_degree, hub.out_degree), (4, 1));
+        assert_eq!(hub.relative_pagerank, 1.0);
+        assert!(scores[&ids[1]].pagerank > scores[&ids[2]].pagerank);
+        assert_eq!(scores[&ids[2]].pagerank, scores[&ids[3]].pagerank);
+        assert!(scores[&ids[5]].relative_pagerank < 1.0);
+
+        assert!(refresh(&storage, &reader, &config).unwrap());
+        assert!(!refresh(&storage, &reader, &config).unwrap(), "refresh with an unchanged graph recomputed");
+        let stored = storage.read().unwrap().get_centrality(ids[0]).unwrap().unwrap();
+        assert_eq!(stored.relative_pagerank, 1.0);
+
+        storage.add_edge_inherent(ids[5], ids[2], "cites".to_string(), 1.0).unwrap();
+        let reader = storage.read().unwrap();
+        assert!(refresh(&storage, &reader, &config).unwrap());
+        let stored = storage.read().unwrap().get_centrality(ids[2]).unwrap().unwrap();
+        assert!(stored.pagerank > scores[&ids[2]].pagerank);
+    }
+}
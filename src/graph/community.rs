@@ -0,0 +1,321 @@
+//! Community detection over the memory graph (Louvain).
+//!
+//! Edges are treated as undirected: both directions between two memories add
+//! up to one link weighted by the sum of their `Edge.weight`s. Memories with
+//! no usable edges form communities of one.
+
+use super::EdgeSource;
+use crate::storage::StorageReader;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CommunityConfig {
+    /// Above 1 favours more, smaller communities; below 1 fewer, larger ones.
+    pub resolution: f32,
+    /// Cap on aggregation levels; each level merges the communities found by the previous one.
+    pub max_levels: usize,
+}
+
+impl Default for CommunityConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 1.0,
+            max_levels: 10,
+        }
+    }
+}
+
+/// Memories assigned to communities, with statistics per community.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Communities {
+    /// Largest first; a community's `id` is its position here
+    pub clusters: Vec<Cluster>,
+    /// Community id of every memory
+    pub membership: HashMap<Uuid, usize>,
+    /// Modularity of the partition at `CommunityConfig.resolution`
+    pub modularity: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cluster {
+    pub id: usize,
+    pub members: Vec<Uuid>,
+    /// Distinct linked pairs inside the cluster over all possible pairs; 0 for a single memory
+    pub density: f32,
+    /// `MemoryType` names with their member counts, most common first
+    pub memory_types: Vec<(String, usize)>,
+    /// Mean of the members' `Memory::embedding`, over the members sharing the
+    /// most common embedding length
+    pub centroid: Vec<f32>,
+}
+
+impl Cluster {
+    pub fn size(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Most common memory type among the members.
+    pub fn dominant_type(&self) -> Option<&str> {
+        self.memory_types.first().map(|(name, _)| name.as_str())
+    }
+}
+
+/// Partitions every memory in `reader`'s snapshot into communities, linked
+/// by the edges in `graph` (the snapshot itself, or an `AdjacencyCache`).
+///
+/// The result is deterministic for a given snapshot: memories are visited
+/// in id order and ties keep a memory where it is.
+pub fn detect<G: EdgeSource + ?Sized>(reader: &StorageReader, graph: &G, config: &CommunityConfig) -> Result<Communities> {
+    let memories = reader.list_memories()?;
+    let position: HashMap<Uuid, usize> = memories.iter().enumerate().map(|(i, m)| (m.id, i)).collect();
+
+    // Undirected adjacency, plus the distinct pairs for density.
+    let mut links = Graph::new(memories.len());
+    let mut pairs: HashSet<(usize, usize)> = HashSet::new();
+    for (source, edges) in graph.all_edges_out()? {
+        let Some(&a) = position.get(&source) else {
+            continue;
+        };
+        for edge in edges {
+            let Some(&b) = position.get(&edge.target_id) else {
+                continue;
+            };
+            if a != b {
+                pairs.insert((a.min(b), a.max(b)));
+            }
+            if edge.weight > 0.0 {
+                links.link(a, b, edge.weight as f64);
+            }
+        }
+    }
+
+    let (assignment, modularity) = louvain(links, config);
+
+    // Renumber so the largest community is 0, ties broken by smallest member id.
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (node, community) in assignment.iter().enumerate() {
+        groups.entry(*community).or_default().push(node);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().collect();
+    for group in &mut groups {
+        group.sort_by_key(|&node| memories[node].id);
+    }
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then(memories[a[0]].id.cmp(&memories[b[0]].id)));
+
+    let mut internal_pairs: HashMap<usize, usize> = HashMap::new();
+    for (a, b) in &pairs {
+        if assignment[*a] == assignment[*b] {
+            *internal_pairs.entry(assignment[*a]).or_default() += 1;
+        }
+    }
+
+    let mut membership = HashMap::with_capacity(memories.len());
+    let mut clusters = Vec::with_capacity(groups.len());
+    for (id, group) in groups.into_iter().enumerate() {
+        let size = group.len();
+        let internal = internal_pairs.get(&assignment[group[0]]).copied().unwrap_or(0);
+        let density = if size > 1 { internal as f32 / (size * (size - 1) / 2) as f32 } else { 0.0 };
+
+        let memory_types = super::memory_type_counts(group.iter().map(|&node| &memories[node]));
+        let centroid = centroid(group.iter().map(|&node| memories[node].embedding.as_slice()));
+
+        let members: Vec<Uuid> = group.iter().map(|&node| memories[node].id).collect();
+        for member in &members {
+            membership.insert(*member, id);
+        }
+        clusters.push(Cluster { id, members, density, memory_types, centroid });
+    }
+
+    Ok(Communities { clusters, membership, modularity: modularity as f32 })
+}
+
+/// Mean of the `embeddings` that have the most common length, ties going to
+/// the length seen first. Vectors of another length, e.g. left behind by an
+/// interrupted re-embed, are skipped rather than truncated into the sum.
+fn centroid<'a>(embeddings: impl Iterator<Item = &'a [f32]> + Clone) -> Vec<f32> {
+    let mut lengths: Vec<(usize, usize)> = Vec::new();
+    for embedding in embeddings.clone() {
+        match lengths.iter_mut().find(|(len, _)| *len == embedding.len()) {
+            Some((_, count)) => *count += 1,
+            None => lengths.push((embedding.len(), 1)),
+        }
+    }
+    let Some(&(dimension, count)) = lengths.iter().rev().max_by_key(|(_, count)| *count) else {
+        return Vec::new();
+    };
+
+    let mut centroid = vec![0.0f32; dimension];
+    for embedding in embeddings.filter(|embedding| embedding.len() == dimension) {
+        for (sum, x) in centroid.iter_mut().zip(embedding) {
+            *sum += x;
+        }
+    }
+    centroid.iter_mut().for_each(|sum| *sum /= count as f32);
+    centroid
+}
+
+/// Symmetric weighted adjacency; `links[i][&i]` holds self-loop weight.
+struct Graph {
+    links: Vec<HashMap<usize, f64>>,
+}
+
+impl Graph {
+    fn new(n: usize) -> Self {
+        Self { links: vec![HashMap::new(); n] }
+    }
+
+    fn link(&mut self, a: usize, b: usize, weight: f64) {
+        *self.links[a].entry(b).or_default() += weight;
+        *self.links[b].entry(a).or_default() += weight;
+    }
+
+    /// Sum of the weights at `node`, self-loops counted from both ends.
+    fn degree(&self, node: usize) -> f64 {
+        self.links[node].values().sum()
+    }
+}
+
+/// Community of each node of `graph` and the partition's modularity.
+fn louvain(mut graph: Graph, config: &CommunityConfig) -> (Vec<usize>, f64) {
+    let resolution = config.resolution as f64;
+    let total: f64 = (0..graph.links.len()).map(|node| graph.degree(node)).sum();
+    // `assignment[i]` is the current node containing original memory `i`.
+    let mut assignment: Vec<usize> = (0..graph.links.len()).collect();
+    if total == 0.0 {
+        return (assignment, 0.0);
+    }
+
+    for _ in 0..config.max_levels {
+        let (community, moved) = local_moves(&graph, resolution, total);
+        if !moved {
+            break;
+        }
+
+        // Aggregate: each community becomes one node.
+        let mut renumber: HashMap<usize, usize> = HashMap::new();
+        for &c in &community {
+            let next = renumber.len();
+            renumber.entry(c).or_insert(next);
+        }
+        let mut aggregated = Graph::new(renumber.len());
+        for (node, neighbours) in graph.links.iter().enumerate() {
+            for (&other, &weight) in neighbours {
+                let (a, b) = (renumber[&community[node]], renumber[&community[other]]);
+                *aggregated.links[a].entry(b).or_default() += weight;
+            }
+        }
+        for slot in &mut assignment {
+            *slot = renumber[&community[*slot]];
+        }
+        graph = aggregated;
+    }
+
+    let modularity = (0..graph.links.len())
+        .map(|node| {
+            let inside = graph.links[node].get(&node).copied().unwrap_or(0.0);
+            let degree = graph.degree(node);
+            inside / total - resolution * (degree / total).powi(2)
+        })
+        .sum();
+    (assignment, modularity)
+}
+
+/// One Louvain phase: moves single nodes between communities while that
+/// raises modularity. Returns each node's community and whether any moved.
+fn local_moves(graph: &Graph, resolution: f64, total: f64) -> (Vec<usize>, bool) {
+    let n = graph.links.len();
+    let degree: Vec<f64> = (0..n).map(|node| graph.degree(node)).collect();
+    let mut community: Vec<usize> = (0..n).collect();
+    // Summed degree of each community.
+    let mut community_degree = degree.clone();
+    let mut moved_any = false;
+
+    loop {
+        let mut moved = false;
+        for node in 0..n {
+            let current = community[node];
+            community_degree[current] -= degree[node];
+
+            let mut weight_to: HashMap<usize, f64> = HashMap::new();
+            for (&other, &weight) in &graph.links[node] {
+                if other != node {
+                    *weight_to.entry(community[other]).or_default() += weight;
+                }
+            }
+            let gain = |c: usize, weight: f64| weight - resolution * community_degree[c] * degree[node] / total;
+
+            let mut best = current;
+            let mut best_gain = gain(current, weight_to.get(&current).copied().unwrap_or(0.0));
+            let mut candidates: Vec<(usize, f64)> = weight_to.into_iter().collect();
+            candidates.sort_by_key(|(c, _)| *c);
+            for (c, weight) in candidates {
+                let g = gain(c, weight);
+                if g > best_gain + 1e-12 {
+                    best = c;
+                    best_gain = g;
+                }
+            }
+
+            community_degree[best] += degree[node];
+            if best != current {
+                community[node] = best;
+                moved = true;
+                moved_any = true;
+            }
+        }
+        if !moved {
+            return (community, moved_any);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::testing::graph;
+
+    #[test]
+    fn louvain_splits_cliques_joined_by_a_bridge() {
+        // Two 4-cliques joined by one bridge, plus an unlinked memory.
+        let mut edges = Vec::new();
+        for clique in [0..4, 4..8] {
+            for a in clique.clone() {
+                for b in clique.clone().filter(|&b| b > a) {
+                    edges.push((a, b, "related", 1.0));
+                }
+            }
+        }
+        edges.push((3, 4, "related", 1.0));
+        let embeddings = (0..9).map(|i| if i < 4 { vec![1.0, 0.0] } else { vec![0.0, 1.0] }).collect();
+        let (storage, ids) = graph(embeddings, &edges);
+
+        let reader = storage.read().unwrap();
+        let communities = detect(&reader, &reader, &CommunityConfig::default()).unwrap();
+        let sizes: Vec<usize> = communities.clusters.iter().map(Cluster::size).collect();
+        assert_eq!(sizes, [4, 4, 1]);
+        assert!(communities.modularity > 0.3, "modularity {}", communities.modularity);
+
+        let left = communities.membership[&ids[0]];
+        let right = communities.membership[&ids[4]];
+        assert_ne!(left, right);
+        assert!(ids[..4].iter().all(|id| communities.membership[id] == left));
+        assert!(ids[4..8].iter().all(|id| communities.membership[id] == right));
+        assert_eq!(communities.clusters[left].density, 1.0);
+        assert_eq!(communities.clusters[left].centroid, [1.0, 0.0]);
+        assert_eq!(communities.clusters[right].centroid, [0.0, 1.0]);
+        assert_eq!(communities.clusters[2].members, [ids[8]]);
+        assert_eq!(communities.clusters[2].density, 0.0);
+    }
+
+    #[test]
+    fn centroid_skips_embeddings_of_another_length() {
+        let embeddings = [vec![1.0, 0.0], vec![9.0, 9.0, 9.0], vec![0.0, 1.0], vec![]];
+        assert_eq!(centroid(embeddings.iter().map(Vec::as_slice)), [0.5, 0.5]);
+        // Ties go to the length seen first.
+        assert_eq!(centroid([[2.0].as_slice(), &[4.0, 4.0]].into_iter()), [2.0]);
+        assert!(centroid(std::iter::empty()).is_empty());
+    }
+}
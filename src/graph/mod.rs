@@ -0,0 +1,113 @@
+//! Algorithms over the memory graph.
+//!
+//! They read adjacency through `EdgeSource`, so they run the same against a
+//! `StorageReader` snapshot or any other copy of the `edges_out` / `edges_in`
+//! lists.
+
+use crate::models::{Edge, InboundEdge, Memory};
+use crate::query::{Direction, PathStep};
+use crate::storage::{StorageManager, StorageReader};
+use anyhow::Result;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub mod centrality;
+pub mod community;
+pub mod orphans;
+pub mod path;
+pub mod suggest;
+
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use path::{EdgeCost, GraphPath, PathOptions, shortest_path};
+
+/// Forward and reverse adjacency lists by memory id.
+pub trait EdgeSource {
+    /// Edges leaving `id`.
+    fn edges_out(&self, id: Uuid) -> Result<Vec<Edge>>;
+    /// Edges pointing at `id`.
+    fn edges_in(&self, id: Uuid) -> Result<Vec<InboundEdge>>;
+    /// Every outbound adjacency list, by source memory in id order.
+    fn all_edges_out(&self) -> Result<Vec<(Uuid, Vec<Edge>)>>;
+}
+
+impl EdgeSource for StorageReader {
+    fn edges_out(&self, id: Uuid) -> Result<Vec<Edge>> {
+        self.get_outbound_edges(id)
+    }
+
+    fn edges_in(&self, id: Uuid) -> Result<Vec<InboundEdge>> {
+        self.get_inbound_edges(id)
+    }
+
+    fn all_edges_out(&self) -> Result<Vec<(Uuid, Vec<Edge>)>> {
+        self.list_outbound_edges()
+    }
+}
+
+/// Where new edges are stored: the store itself, or an `AdjacencyCache` that
+/// writes through to it and keeps its copy in step.
+pub trait EdgeWriter {
+    fn add_edge(&self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()>;
+}
+
+impl EdgeWriter for StorageManager {
+    fn add_edge(&self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()> {
+        self.add_edge_inherent(source, target, relation_type, weight)
+    }
+}
+
+/// Whether a relation passes an `edge_types` allow-list (everything when
+/// unset) and an `exclude_edge_types` deny-list, the deny-list winning.
+pub(crate) fn follows(edge_types: Option<&[String]>, exclude_edge_types: Option<&[String]>, relation: &str) -> bool {
+    edge_types.is_none_or(|types| types.iter().any(|t| t == relation))
+        && !exclude_edge_types.is_some_and(|types| types.iter().any(|t| t == relation))
+}
+
+/// Edges one hop from `id` in `direction` whose relation type passes
+/// `follows`, oriented away from `id`.
+pub fn steps<G: EdgeSource + ?Sized>(
+    graph: &G,
+    id: Uuid,
+    direction: Direction,
+    follows: impl Fn(&str) -> bool,
+) -> Result<Vec<PathStep>> {
+    let mut steps = Vec::new();
+    if matches!(direction, Direction::Outbound | Direction::Both) {
+        steps.extend(graph.edges_out(id)?.into_iter()
+            .filter(|edge| follows(&edge.relation_type))
+            .map(|edge| PathStep {
+                from: id,
+                to: edge.target_id,
+                relation_type: edge.relation_type,
+                weight: edge.weight,
+                direction: Direction::Outbound,
+            }));
+    }
+    if matches!(direction, Direction::Inbound | Direction::Both) {
+        steps.extend(graph.edges_in(id)?.into_iter()
+            .filter(|edge| follows(&edge.relation_type))
+            .map(|edge| PathStep {
+                from: id,
+                to: edge.source_id,
+                relation_type: edge.relation_type,
+                weight: edge.weight,
+                direction: Direction::Inbound,
+            }));
+    }
+    Ok(steps)
+}
+
+/// `MemoryType` names among `memories` with their counts, most common first.
+pub(crate) fn memory_type_counts<'a>(memories: impl IntoIterator<Item = &'a Memory>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for memory in memories {
+        *counts.entry(memory.memory_type.name()).or_default() += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
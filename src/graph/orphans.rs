@@ -0,0 +1,275 @@
+//! Orphan knowledge: memories the agent can recall by similarity but can't
+//! reason about through the graph.
+
+use super::EdgeSource;
+use crate::index::{Metric, VectorIndex};
+use crate::storage::StorageReader;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OrphanConfig {
+    /// Largest component, in memories, reported as a fragment
+    pub max_component_size: usize,
+    /// Edges, counting both directions, that make a memory well connected
+    pub min_degree: usize,
+    /// Similarity to a well-connected memory from which an isolated memory
+    /// counts as vector-reachable. `None` means 0.8 under `Metric::Cosine`;
+    /// other metrics score on their own scales and must set it.
+    pub min_similarity: Option<f32>,
+}
+
+impl Default for OrphanConfig {
+    fn default() -> Self {
+        Self {
+            max_component_size: 5,
+            min_degree: 3,
+            min_similarity: None,
+        }
+    }
+}
+
+impl OrphanConfig {
+    /// `min_similarity` for an index scoring with `metric`.
+    pub fn min_similarity(&self, metric: Metric) -> Result<f32> {
+        match (self.min_similarity, metric) {
+            (Some(min_similarity), _) => Ok(min_similarity),
+            (None, Metric::Cosine) => Ok(0.8),
+            (None, metric) => bail!("min_similarity has no default under {metric:?}; set it for this index's scale"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrphanReport {
+    /// Memories with no edges in either direction
+    pub isolated: Vec<Uuid>,
+    /// Components of 2 to `max_component_size` memories cut off from the
+    /// main graph, largest first. Every component tied for the largest size
+    /// counts as the main graph, so none of them is reported.
+    pub fragments: Vec<Fragment>,
+    /// Isolated memories close in vector space to a well-connected memory,
+    /// most similar first
+    pub vector_isolated: Vec<VectorOrphan>,
+}
+
+/// A small connected component, edges taken as undirected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fragment {
+    pub members: Vec<Uuid>,
+    /// `MemoryType` names with their member counts, most common first
+    pub memory_types: Vec<(String, usize)>,
+}
+
+impl Fragment {
+    pub fn size(&self) -> usize {
+        self.members.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VectorOrphan {
+    pub id: Uuid,
+    /// Most similar well-connected memory
+    pub nearest: Uuid,
+    pub similarity: f32,
+}
+
+/// Degree of every memory and the connected components of the graph, edges
+/// taken as undirected. Components are largest first, then by smallest member
+/// id; members are in id order.
+pub(crate) struct Connectivity {
+    pub(crate) degree: HashMap<Uuid, usize>,
+    pub(crate) components: Vec<Vec<Uuid>>,
+}
+
+impl Connectivity {
+    /// Memories are those in `reader`'s snapshot, edges those in `graph`.
+    pub(crate) fn of<G: EdgeSource + ?Sized>(reader: &StorageReader, graph: &G) -> Result<Self> {
+        let ids = reader.memory_ids()?;
+        let position: HashMap<Uuid, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        let mut degree = vec![0usize; ids.len()];
+        let mut parent: Vec<usize> = (0..ids.len()).collect();
+        for (source, edges) in graph.all_edges_out()? {
+            let Some(&a) = position.get(&source) else {
+                continue;
+            };
+            for edge in edges {
+                let Some(&b) = position.get(&edge.target_id) else {
+                    continue;
+                };
+                degree[a] += 1;
+                degree[b] += 1;
+                let (root_a, root_b) = (find(&mut parent, a), find(&mut parent, b));
+                parent[root_a.max(root_b)] = root_a.min(root_b);
+            }
+        }
+
+        let mut components: HashMap<usize, Vec<Uuid>> = HashMap::new();
+        for (i, id) in ids.iter().enumerate() {
+            components.entry(find(&mut parent, i)).or_default().push(*id);
+        }
+        let mut components: Vec<Vec<Uuid>> = components.into_values().collect();
+        components.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+        Ok(Self {
+            degree: ids.into_iter().zip(degree).collect(),
+            components,
+        })
+    }
+
+    /// Number of leading `components` tied for the largest size, which
+    /// together make up the main graph.
+    pub(crate) fn main_components(&self) -> usize {
+        let largest = self.components.first().map_or(0, Vec::len);
+        self.components.iter().take_while(|members| members.len() == largest).count()
+    }
+
+    /// Memories with at least `min_degree` edges.
+    pub(crate) fn well_connected(&self, min_degree: usize) -> HashSet<Uuid> {
+        self.degree.iter()
+            .filter(|(_, degree)| **degree >= min_degree.max(1))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+/// Union-find root of `node`, halving the path on the way.
+fn find(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+/// Finds orphaned memories in `reader`'s snapshot, connected by the edges in
+/// `graph` (the snapshot itself, or an `AdjacencyCache`).
+///
+/// `index` is the vector index over `Memory::embedding`; it is searched once
+/// per isolated memory, restricted to the well-connected memories. Fails if
+/// `config.min_similarity` is unset and the index doesn't use cosine.
+pub fn detect<G: EdgeSource + ?Sized, V: VectorIndex + ?Sized>(
+    reader: &StorageReader,
+    graph: &G,
+    index: &V,
+    config: &OrphanConfig,
+) -> Result<OrphanReport> {
+    let min_similarity = config.min_similarity(index.metric())?;
+    let connectivity = Connectivity::of(reader, graph)?;
+
+    let mut isolated: Vec<Uuid> = connectivity.degree.iter()
+        .filter(|(_, degree)| **degree == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    isolated.sort();
+
+    let mut fragments = Vec::new();
+    for members in connectivity.components.iter().skip(connectivity.main_components()) {
+        if members.len() < 2 || members.len() > config.max_component_size {
+            continue;
+        }
+        let mut memories = Vec::with_capacity(members.len());
+        for id in members {
+            memories.extend(reader.get_memory(*id)?);
+        }
+        fragments.push(Fragment {
+            members: members.clone(),
+            memory_types: super::memory_type_counts(&memories),
+        });
+    }
+
+    let well_connected = connectivity.well_connected(config.min_degree);
+    let mut vector_isolated = Vec::new();
+    if !well_connected.is_empty() {
+        for id in &isolated {
+            let Some(memory) = reader.get_memory(*id)? else {
+                continue;
+            };
+            let nearest = index.search_filtered(&memory.embedding, 1, &|candidate| well_connected.contains(&candidate))?;
+            if let Some(&(nearest, similarity)) = nearest.first()
+                && similarity >= min_similarity
+            {
+                vector_isolated.push(VectorOrphan { id: *id, nearest, similarity });
+            }
+        }
+    }
+    vector_isolated.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then(a.id.cmp(&b.id)));
+
+    Ok(OrphanReport { isolated, fragments, vector_isolated })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::testing::{graph, unembedded};
+    use crate::index::testing::ground_truth;
+
+    #[test]
+    fn reports_isolated_fragments_and_vector_reachable_memories() {
+        let embeddings = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.9, 0.1, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 1.0, 1.0],
+            vec![0.0, 1.0, 1.0],
+            // Isolated: one next to the hub, one pointing away from everything.
+            vec![0.95, 0.05, 0.0],
+            vec![-1.0, 0.0, 0.0],
+        ];
+        let edges = [
+            (0, 1, "related", 1.0),
+            (0, 2, "related", 1.0),
+            (3, 0, "related", 1.0),
+            (1, 2, "related", 1.0),
+            (4, 5, "related", 1.0),
+        ];
+        let (storage, ids) = graph(embeddings.clone(), &edges);
+        let index = ground_truth(Metric::Cosine, &ids, &embeddings);
+        let reader = storage.read().unwrap();
+
+        let report = detect(&reader, &reader, &index, &OrphanConfig::default()).unwrap();
+        let mut isolated = vec![ids[6], ids[7]];
+        isolated.sort();
+        assert_eq!(report.isolated, isolated);
+        assert_eq!(report.fragments.len(), 1);
+        let mut fragment = vec![ids[4], ids[5]];
+        fragment.sort();
+        assert_eq!(report.fragments[0].members, fragment);
+        assert_eq!(report.fragments[0].memory_types, [("Semantic".to_string(), 2)]);
+        assert_eq!(report.vector_isolated.len(), 1);
+        assert_eq!((report.vector_isolated[0].id, report.vector_isolated[0].nearest), (ids[6], ids[0]));
+
+        let euclidean = ground_truth(Metric::Euclidean, &ids, &embeddings);
+        assert!(detect(&reader, &reader, &euclidean, &OrphanConfig::default()).is_err());
+        let config = OrphanConfig { min_similarity: Some(0.9), ..OrphanConfig::default() };
+        let report = detect(&reader, &reader, &euclidean, &config).unwrap();
+        assert_eq!(report.vector_isolated.len(), 1);
+        assert_eq!(report.vector_isolated[0].id, ids[6]);
+    }
+
+    #[test]
+    fn components_tied_for_largest_are_all_main() {
+        // Two triangles and a pair: only the pair is a fragment, whichever
+        // triangle holds the smallest id.
+        let (storage, ids) = unembedded(8, &[
+            (0, 1, "related", 1.0),
+            (1, 2, "related", 1.0),
+            (2, 0, "related", 1.0),
+            (3, 4, "related", 1.0),
+            (4, 5, "related", 1.0),
+            (5, 3, "related", 1.0),
+            (6, 7, "related", 1.0),
+        ]);
+        let index = ground_truth(Metric::Cosine, &ids, &vec![vec![1.0, 0.0]; 8]);
+        let reader = storage.read().unwrap();
+        let report = detect(&reader, &reader, &index, &OrphanConfig::default()).unwrap();
+        assert!(report.isolated.is_empty());
+        assert_eq!(report.fragments.len(), 1);
+        assert!(report.fragments[0].members.iter().all(|id| *id == ids[6] || *id == ids[7]));
+    }
+}
@@ -0,0 +1,187 @@
+//! Weighted shortest paths between two memories (Dijkstra).
+
+use super::EdgeSource;
+use crate::query::{Direction, PathStep};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use uuid::Uuid;
+
+/// How an edge's `weight` (a strength, higher is closer) turns into a path cost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeCost {
+    /// `1 / weight`: strong edges are short.
+    #[default]
+    InverseWeight,
+    /// `-ln(weight)`, weights capped at 1: the cheapest path is the one whose
+    /// weights have the largest product, i.e. the strongest chain.
+    NegativeLog,
+    /// Every edge costs 1: the path with the fewest hops.
+    Hops,
+}
+
+impl EdgeCost {
+    /// Cost of an edge with `weight`, or `None` if it can't be followed.
+    /// Edges with a non-positive weight are only followed under `Hops`.
+    fn of(self, weight: f32) -> Option<f32> {
+        match self {
+            EdgeCost::Hops => Some(1.0),
+            _ if weight.is_nan() || weight <= 0.0 => None,
+            EdgeCost::InverseWeight => Some(1.0 / weight),
+            EdgeCost::NegativeLog => Some(-weight.min(1.0).ln()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PathOptions {
+    /// Which way edges may be followed; `Both` ignores edge direction
+    #[serde(default)]
+    pub direction: Direction,
+    /// Only follow these relation types; all types when unset
+    #[serde(default)]
+    pub edge_types: Option<Vec<String>>,
+    /// Never follow these relation types, even if listed in `edge_types`
+    #[serde(default)]
+    pub exclude_edge_types: Option<Vec<String>>,
+    #[serde(default)]
+    pub cost: EdgeCost,
+}
+
+/// A path from one memory to another.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GraphPath {
+    /// Memory ids along the path, start first and end last
+    pub memories: Vec<Uuid>,
+    /// Edge between each consecutive pair of `memories`, oriented along the path
+    pub steps: Vec<PathStep>,
+    /// Sum of the edge costs under `PathOptions.cost`
+    pub cost: f32,
+}
+
+/// Cheapest path from `from` to `to`, or `None` if `to` can't be reached
+/// under `options`. A memory's path to itself is empty with cost 0.
+///
+/// Where parallel edges join the same two memories, the cheapest one is used.
+pub fn shortest_path<G: EdgeSource + ?Sized>(graph: &G, from: Uuid, to: Uuid, options: &PathOptions) -> Result<Option<GraphPath>> {
+    let follows = |relation: &str| {
+        super::follows(options.edge_types.as_deref(), options.exclude_edge_types.as_deref(), relation)
+    };
+
+    let mut best: HashMap<Uuid, f32> = HashMap::from([(from, 0.0)]);
+    // Edge each reached memory was last improved through.
+    let mut via: HashMap<Uuid, PathStep> = HashMap::new();
+    let mut settled: HashSet<Uuid> = HashSet::new();
+    let mut queue = BinaryHeap::from([Reverse(Frontier { cost: 0.0, node: from })]);
+
+    while let Some(Reverse(Frontier { cost, node })) = queue.pop() {
+        if !settled.insert(node) {
+            continue;
+        }
+        if node == to {
+            return Ok(Some(unwind(from, to, cost, via)));
+        }
+        for step in super::steps(graph, node, options.direction, follows)? {
+            let Some(edge_cost) = options.cost.of(step.weight) else {
+                continue;
+            };
+            let next = cost + edge_cost;
+            if settled.contains(&step.to) || best.get(&step.to).is_some_and(|&known| known <= next) {
+                continue;
+            }
+            best.insert(step.to, next);
+            queue.push(Reverse(Frontier { cost: next, node: step.to }));
+            via.insert(step.to, step);
+        }
+    }
+    Ok(None)
+}
+
+/// Follows `via` back from `to` and returns the path in forward order.
+fn unwind(from: Uuid, to: Uuid, cost: f32, mut via: HashMap<Uuid, PathStep>) -> GraphPath {
+    let mut steps = Vec::new();
+    let mut node = to;
+    while node != from {
+        let step = via.remove(&node).expect("every reached memory but the start has an incoming step");
+        node = step.from;
+        steps.push(step);
+    }
+    steps.reverse();
+
+    let mut memories = Vec::with_capacity(steps.len() + 1);
+    memories.push(from);
+    memories.extend(steps.iter().map(|step| step.to));
+    GraphPath { memories, steps, cost }
+}
+
+/// Queue entry; ordered by cost, then id so pops are deterministic.
+struct Frontier {
+    cost: f32,
+    node: Uuid,
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cost.total_cmp(&other.cost).then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::testing::unembedded;
+
+    #[test]
+    fn cheapest_path_under_each_cost_and_filter() {
+        // 0 -> 1 -> 3 is strong, 0 -> 3 is a weak shortcut, 0 -> 2 -> 3 is weak.
+        let (storage, ids) = unembedded(5, &[
+            (0, 1, "causes", 1.0),
+            (1, 3, "causes", 1.0),
+            (0, 3, "relates_to", 0.2),
+            (0, 2, "relates_to", 0.1),
+            (2, 3, "relates_to", 0.9),
+        ]);
+        let reader = storage.read().unwrap();
+        let path = |from: usize, to: usize, options: PathOptions| {
+            shortest_path(&reader, ids[from], ids[to], &options).unwrap()
+                .map(|path| (path.memories.iter().map(|id| ids.iter().position(|i| i == id).unwrap()).collect::<Vec<_>>(), path.cost))
+        };
+
+        assert_eq!(path(0, 3, PathOptions::default()), Some((vec![0, 1, 3], 2.0)));
+        assert_eq!(path(0, 3, PathOptions { cost: EdgeCost::NegativeLog, ..Default::default() }), Some((vec![0, 1, 3], 0.0)));
+        assert_eq!(path(0, 3, PathOptions { cost: EdgeCost::Hops, ..Default::default() }), Some((vec![0, 3], 1.0)));
+
+        let excluded = PathOptions { exclude_edge_types: Some(vec!["causes".to_string()]), ..Default::default() };
+        assert_eq!(path(0, 3, excluded), Some((vec![0, 3], 5.0)));
+        let only = PathOptions { edge_types: Some(vec!["causes".to_string()]), ..Default::default() };
+        assert_eq!(path(0, 2, only), None);
+
+        // Edges are directed unless `Both` is asked for.
+        assert_eq!(path(3, 0, PathOptions::default()), None);
+        let both = PathOptions { direction: Direction::Both, ..Default::default() };
+        assert_eq!(path(3, 0, both), Some((vec![3, 1, 0], 2.0)));
+        assert_eq!(path(0, 4, PathOptions::default()), None);
+        assert_eq!(path(2, 2, PathOptions::default()), Some((vec![2], 0.0)));
+
+        let steps = shortest_path(&reader, ids[3], ids[0], &PathOptions { direction: Direction::Both, ..Default::default() })
+            .unwrap().unwrap().steps;
+        assert!(steps.iter().all(|step| step.direction == Direction::Inbound));
+        assert_eq!((steps[0].from, steps[0].to), (ids[3], ids[1]));
+    }
+}
@@ -0,0 +1,255 @@
+//! Link prediction for orphaned memories.
+//!
+//! Each orphan is matched to its nearest well-connected memories in vector
+//! space. The proposed relation is the one those neighbours most commonly
+//! take part in, in the same direction, so a repaired orphan looks like the
+//! memories it is joined to.
+
+use super::{EdgeSource, EdgeWriter};
+use super::orphans::{Connectivity, OrphanConfig};
+use crate::index::VectorIndex;
+use crate::storage::StorageReader;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestConfig {
+    /// What counts as an orphan and as well connected; its `min_similarity`
+    /// is also the lowest similarity a suggestion can have
+    pub orphans: OrphanConfig,
+    /// Suggestions per orphan
+    pub candidates: usize,
+    /// Relation proposed when a neighbour has no edges to learn from
+    pub default_relation: String,
+}
+
+impl Default for SuggestConfig {
+    fn default() -> Self {
+        Self {
+            orphans: OrphanConfig::default(),
+            candidates: 3,
+            default_relation: "relates_to".to_string(),
+        }
+    }
+}
+
+/// A proposed edge joining an orphan to a well-connected memory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkSuggestion {
+    /// The orphan being linked
+    pub orphan: Uuid,
+    pub source: Uuid,
+    pub target: Uuid,
+    pub relation_type: String,
+    /// Similarity between the orphan and the neighbour, used as the edge weight
+    pub score: f32,
+    /// Share of the neighbour's edges with this relation and direction; 0
+    /// when it fell back to `default_relation`
+    pub support: f32,
+}
+
+/// Suggests edges for every isolated memory and every member of a small
+/// fragment, best first.
+///
+/// Memories come from `reader`'s snapshot and edges from `graph` (the
+/// snapshot itself, or an `AdjacencyCache`). `index` is the vector index
+/// over `Memory::embedding`. Fragment members are
+/// only linked to well-connected memories outside their own fragment. Fails
+/// if `config.orphans.min_similarity` is unset and the index doesn't use cosine.
+pub fn suggest_links<G: EdgeSource + ?Sized, V: VectorIndex + ?Sized>(
+    reader: &StorageReader,
+    graph: &G,
+    index: &V,
+    config: &SuggestConfig,
+) -> Result<Vec<LinkSuggestion>> {
+    let min_similarity = config.orphans.min_similarity(index.metric())?;
+    let connectivity = Connectivity::of(reader, graph)?;
+    let well_connected = connectivity.well_connected(config.orphans.min_degree);
+    if well_connected.is_empty() || config.candidates == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut orphans: Vec<(Uuid, HashSet<Uuid>)> = Vec::new();
+    let main = connectivity.main_components();
+    for (i, members) in connectivity.components.iter().enumerate() {
+        let orphaned = members.len() == 1 || (i >= main && members.len() <= config.orphans.max_component_size);
+        if orphaned {
+            let component: HashSet<Uuid> = members.iter().copied().collect();
+            orphans.extend(members.iter().map(|id| (*id, component.clone())));
+        }
+    }
+
+    let mut relations: HashMap<Uuid, Option<(String, Orientation, f32)>> = HashMap::new();
+    let mut suggestions = Vec::new();
+    for (orphan, component) in orphans {
+        let Some(memory) = reader.get_memory(orphan)? else {
+            continue;
+        };
+        let allowed = |id: Uuid| well_connected.contains(&id) && !component.contains(&id);
+        for (neighbour, similarity) in index.search_filtered(&memory.embedding, config.candidates, &allowed)? {
+            if similarity < min_similarity {
+                break;
+            }
+            let relation = match relations.get(&neighbour) {
+                Some(relation) => relation.clone(),
+                None => {
+                    let relation = dominant_relation(graph, neighbour)?;
+                    relations.insert(neighbour, relation.clone());
+                    relation
+                }
+            };
+            let (relation_type, orientation, support) = relation
+                .unwrap_or_else(|| (config.default_relation.clone(), Orientation::FromOrphan, 0.0));
+            let (source, target) = match orientation {
+                Orientation::FromOrphan => (orphan, neighbour),
+                Orientation::ToOrphan => (neighbour, orphan),
+            };
+            suggestions.push(LinkSuggestion { orphan, source, target, relation_type, score: similarity, support });
+        }
+    }
+    suggestions.sort_by(|a, b| {
+        b.score.total_cmp(&a.score).then(a.orphan.cmp(&b.orphan)).then(a.target.cmp(&b.target))
+    });
+    Ok(suggestions)
+}
+
+/// Adds every suggestion scoring at least `min_score` as an edge weighted by
+/// its score, and returns the ones applied. Pass the `AdjacencyCache` rather
+/// than the store when one is in use, so it takes the edges in place.
+///
+/// Edge weights are strengths in `(0, 1]`, so scores above 1 (possible under
+/// the dot product) are capped at 1, and suggestions scoring 0 or less are
+/// never applied whatever `min_score` is.
+pub fn apply<W: EdgeWriter + ?Sized>(graph: &W, suggestions: &[LinkSuggestion], min_score: f32) -> Result<Vec<LinkSuggestion>> {
+    let mut applied = Vec::new();
+    for suggestion in suggestions.iter().filter(|s| s.score >= min_score && s.score > 0.0) {
+        let weight = suggestion.score.min(1.0);
+        graph.add_edge(suggestion.source, suggestion.target, suggestion.relation_type.clone(), weight)?;
+        applied.push(suggestion.clone());
+    }
+    Ok(applied)
+}
+
+/// Which way a suggested edge runs, mirroring the neighbour's edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Orientation {
+    /// The neighbour usually receives this relation: `orphan -> neighbour`
+    FromOrphan,
+    /// The neighbour usually emits this relation: `neighbour -> orphan`
+    ToOrphan,
+}
+
+/// Most common relation and direction among `id`'s edges with its share of
+/// them, or `None` if it has no edges.
+fn dominant_relation<G: EdgeSource + ?Sized>(graph: &G, id: Uuid) -> Result<Option<(String, Orientation, f32)>> {
+    let mut counts: HashMap<(String, Orientation), usize> = HashMap::new();
+    for edge in graph.edges_out(id)? {
+        *counts.entry((edge.relation_type, Orientation::ToOrphan)).or_default() += 1;
+    }
+    for edge in graph.edges_in(id)? {
+        *counts.entry((edge.relation_type, Orientation::FromOrphan)).or_default() += 1;
+    }
+    let total: usize = counts.values().sum();
+    Ok(counts.into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+        .map(|((relation, orientation), count)| (relation, orientation, count as f32 / total as f32)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::testing::graph;
+    use crate::index::Metric;
+    use crate::index::testing::ground_truth;
+
+    #[test]
+    fn suggests_the_neighbours_relation_and_applies_it() {
+        let embeddings = vec![
+            // A hub that three memories are part of.
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 1.0, 0.1],
+            vec![0.0, 1.0, 0.2],
+            // An isolated memory and a fragment, both close to the hub.
+            vec![0.9, 0.1, 0.0],
+            vec![0.9, 0.0, 0.3],
+            vec![0.9, 0.0, 0.4],
+            // Isolated and dissimilar to everything well connected.
+            vec![-1.0, 0.0, 0.0],
+        ];
+        let edges = [
+            (1, 0, "part_of", 1.0),
+            (2, 0, "part_of", 1.0),
+            (3, 0, "part_of", 1.0),
+            (5, 6, "related", 1.0),
+        ];
+        let (storage, ids) = graph(embeddings.clone(), &edges);
+        let index = ground_truth(Metric::Cosine, &ids, &embeddings);
+        let config = SuggestConfig::default();
+
+        let reader = storage.read().unwrap();
+        let suggestions = suggest_links(&reader, &reader, &index, &config).unwrap();
+        let mut orphans: Vec<Uuid> = suggestions.iter().map(|s| s.orphan).collect();
+        orphans.sort();
+        let mut expected = vec![ids[4], ids[5], ids[6]];
+        expected.sort();
+        assert_eq!(orphans, expected);
+        assert!(suggestions.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        for suggestion in &suggestions {
+            assert_eq!((suggestion.source, suggestion.target), (suggestion.orphan, ids[0]));
+            assert_eq!(suggestion.relation_type, "part_of");
+            assert_eq!(suggestion.support, 1.0);
+        }
+
+        let best = suggestions[0].score;
+        let applied = apply(&*storage, &suggestions, best).unwrap();
+        assert_eq!(applied, &suggestions[..1]);
+        let reader = storage.read().unwrap();
+        let edges = reader.get_outbound_edges(applied[0].orphan).unwrap();
+        assert!(edges.iter().any(|edge| edge.target_id == ids[0] && edge.relation_type == "part_of" && edge.weight == best));
+
+        let remaining = suggest_links(&reader, &reader, &index, &config).unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining.iter().all(|s| s.orphan != applied[0].orphan));
+    }
+
+    #[test]
+    fn non_cosine_indexes_need_an_explicit_threshold() {
+        let (storage, ids) = graph(vec![vec![1.0, 0.0]; 3], &[(0, 1, "related", 1.0)]);
+        let index = ground_truth(Metric::DotProduct, &ids, &vec![vec![1.0, 0.0]; 3]);
+        let reader = storage.read().unwrap();
+        assert!(suggest_links(&reader, &reader, &index, &SuggestConfig::default()).is_err());
+
+        let mut config = SuggestConfig::default();
+        config.orphans.min_degree = 1;
+        config.orphans.min_similarity = Some(0.5);
+        let suggestions = suggest_links(&reader, &reader, &index, &config).unwrap();
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions.iter().all(|s| s.orphan == ids[2] && s.score == 1.0));
+    }
+
+    #[test]
+    fn applied_weights_stay_within_edge_strengths() {
+        let (storage, ids) = graph(vec![vec![1.0, 0.0]; 5], &[]);
+        let suggestion = |orphan: usize, score: f32| LinkSuggestion {
+            orphan: ids[orphan],
+            source: ids[orphan],
+            target: ids[0],
+            relation_type: "related".to_string(),
+            score,
+            support: 1.0,
+        };
+        let suggestions = [suggestion(1, 2.5), suggestion(2, 0.4), suggestion(3, 0.0), suggestion(4, -0.3)];
+
+        let applied = apply(&*storage, &suggestions, f32::NEG_INFINITY).unwrap();
+        assert_eq!(applied, &suggestions[..2]);
+        let reader = storage.read().unwrap();
+        let weight = |orphan: usize| reader.get_outbound_edges(ids[orphan]).unwrap().iter().map(|edge| edge.weight).collect::<Vec<_>>();
+        assert_eq!(weight(1), [1.0]);
+        assert_eq!(weight(2), [0.4]);
+        assert!(weight(3).is_empty());
+        assert!(weight(4).is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+//! Small stored graphs for the graph algorithm tests.
+
+use crate::index::testing::memory;
+use crate::storage::TempStorage;
+use uuid::Uuid;
+
+/// A store with one memory per embedding and the given
+/// `(source, target, relation, weight)` edges between them by position.
+pub(crate) fn graph(embeddings: Vec<Vec<f32>>, edges: &[(usize, usize, &str, f32)]) -> (TempStorage, Vec<Uuid>) {
+    let storage = TempStorage::new();
+    let mut ids = Vec::with_capacity(embeddings.len());
+    for embedding in embeddings {
+        let memory = memory(embedding);
+        storage.save_memory(&memory).unwrap();
+        ids.push(memory.id);
+    }
+    for &(source, target, relation, weight) in edges {
+        storage.add_edge_inherent(ids[source], ids[target], relation.to_string(), weight).unwrap();
+    }
+    (storage, ids)
+}
+
+/// `graph` where the embeddings don't matter.
+pub(crate) fn unembedded(n: usize, edges: &[(usize, usize, &str, f32)]) -> (TempStorage, Vec<Uuid>) {
+    graph(vec![vec![1.0, 0.0]; n], edges)
+}
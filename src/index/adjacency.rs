@@ -0,0 +1,256 @@
+//! In-memory copy of the `edges_out` / `edges_in` tables.
+//!
+//! Loaded once from redb, then kept in step by writing edges through the
+//! cache, so traversals read adjacency from the heap instead of opening a
+//! read transaction and decoding an edge list per node. Writes made on the
+//! store directly move `StorageManager::graph_revision` past the cache's,
+//! and the next lookup reloads.
+
+use super::GraphIndex;
+use crate::graph::{EdgeSource, EdgeWriter};
+use crate::models::{Edge, InboundEdge};
+use crate::storage::StorageManager;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use uuid::Uuid;
+
+#[derive(Default)]
+struct Adjacency {
+    forward: HashMap<Uuid, Vec<Edge>>,
+    reverse: HashMap<Uuid, Vec<InboundEdge>>,
+    /// `graph_revision` of the store these lists reflect
+    revision: u64,
+}
+
+impl Adjacency {
+    /// Every edge in `storage`, read from one snapshot.
+    fn snapshot(storage: &StorageManager) -> Result<Self> {
+        let reader = storage.read()?;
+        let mut adjacency = Self { revision: reader.graph_revision()?, ..Self::default() };
+        for (source, edges) in reader.list_outbound_edges()? {
+            for edge in edges {
+                adjacency.insert(source, edge);
+            }
+        }
+        Ok(adjacency)
+    }
+
+    fn insert(&mut self, source: Uuid, edge: Edge) {
+        self.reverse.entry(edge.target_id).or_default().push(InboundEdge {
+            source_id: source,
+            relation_type: edge.relation_type.clone(),
+            weight: edge.weight,
+            created_at: edge.created_at,
+        });
+        self.forward.entry(source).or_default().push(edge);
+    }
+
+    fn remove_memory(&mut self, id: Uuid) {
+        for edge in self.forward.remove(&id).unwrap_or_default() {
+            if let Some(inbound) = self.reverse.get_mut(&edge.target_id) {
+                inbound.retain(|e| e.source_id != id);
+            }
+        }
+        for edge in self.reverse.remove(&id).unwrap_or_default() {
+            if let Some(outbound) = self.forward.get_mut(&edge.source_id) {
+                outbound.retain(|e| e.target_id != id);
+            }
+        }
+    }
+}
+
+/// Forward and reverse adjacency of the whole graph, shared behind an `RwLock`.
+///
+/// Edges added and memories deleted through the cache update it in place.
+/// Any other change to the graph, such as `graph::suggest::apply` given the
+/// store rather than the cache, is picked up by reloading every edge on the
+/// next lookup.
+pub struct AdjacencyCache {
+    storage: Arc<StorageManager>,
+    adjacency: RwLock<Adjacency>,
+}
+
+impl AdjacencyCache {
+    /// Loads every edge list from `storage`.
+    pub fn load(storage: Arc<StorageManager>) -> Result<Self> {
+        let cache = Self { storage, adjacency: RwLock::new(Adjacency::default()) };
+        cache.reload()?;
+        Ok(cache)
+    }
+
+    /// Replaces the cached adjacency with the store's current edges.
+    pub fn reload(&self) -> Result<()> {
+        *self.write() = Adjacency::snapshot(&self.storage)?;
+        Ok(())
+    }
+
+    /// `graph_revision` of the store the cached edges reflect.
+    pub fn revision(&self) -> u64 {
+        self.read().revision
+    }
+
+    /// Stores an edge and adds it to the cache.
+    pub fn add_edge(&self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()> {
+        // Hold the lock across the write so the cache applies edges in commit order.
+        let mut adjacency = self.write();
+        let edge = self.storage.insert_edge(source, target, relation_type, weight)?;
+        self.apply(&mut adjacency, |adjacency| adjacency.insert(source, edge))
+    }
+
+    /// Deletes a memory from the store and drops its edges from the cache,
+    /// see `StorageManager::delete_memory`.
+    pub fn delete_memory(&self, id: Uuid) -> Result<bool> {
+        let mut adjacency = self.write();
+        let existed = self.storage.delete_memory(id)?;
+        self.apply(&mut adjacency, |adjacency| adjacency.remove_memory(id))?;
+        Ok(existed)
+    }
+
+    /// Number of cached edges.
+    pub fn edge_count(&self) -> Result<usize> {
+        Ok(self.fresh()?.forward.values().map(Vec::len).sum())
+    }
+
+    /// Applies a write the cache just made to the store, or reloads if it
+    /// wasn't the only change since the cached revision.
+    fn apply(&self, adjacency: &mut Adjacency, change: impl FnOnce(&mut Adjacency)) -> Result<()> {
+        let revision = self.storage.graph_revision()?;
+        if revision == adjacency.revision + 1 {
+            change(adjacency);
+            adjacency.revision = revision;
+        } else if revision != adjacency.revision {
+            *adjacency = Adjacency::snapshot(&self.storage)?;
+        }
+        Ok(())
+    }
+
+    /// The cached adjacency, reloaded first if the graph changed behind the cache.
+    fn fresh(&self) -> Result<RwLockReadGuard<'_, Adjacency>> {
+        let revision = self.storage.graph_revision()?;
+        {
+            let adjacency = self.read();
+            if adjacency.revision >= revision {
+                return Ok(adjacency);
+            }
+        }
+        {
+            let mut adjacency = self.write();
+            if adjacency.revision < revision {
+                *adjacency = Adjacency::snapshot(&self.storage)?;
+            }
+        }
+        Ok(self.read())
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Adjacency> {
+        self.adjacency.read().expect("adjacency lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Adjacency> {
+        self.adjacency.write().expect("adjacency lock poisoned")
+    }
+}
+
+impl EdgeSource for AdjacencyCache {
+    fn edges_out(&self, id: Uuid) -> Result<Vec<Edge>> {
+        Ok(self.fresh()?.forward.get(&id).cloned().unwrap_or_default())
+    }
+
+    fn edges_in(&self, id: Uuid) -> Result<Vec<InboundEdge>> {
+        Ok(self.fresh()?.reverse.get(&id).cloned().unwrap_or_default())
+    }
+
+    fn all_edges_out(&self) -> Result<Vec<(Uuid, Vec<Edge>)>> {
+        let mut lists: Vec<(Uuid, Vec<Edge>)> = self.fresh()?.forward.iter()
+            .map(|(id, edges)| (*id, edges.clone()))
+            .collect();
+        lists.sort_by_key(|(id, _)| *id);
+        Ok(lists)
+    }
+}
+
+impl EdgeWriter for AdjacencyCache {
+    fn add_edge(&self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()> {
+        AdjacencyCache::add_edge(self, source, target, relation_type, weight)
+    }
+}
+
+impl GraphIndex for AdjacencyCache {
+    fn add_edge(&mut self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()> {
+        AdjacencyCache::add_edge(self, source, target, relation_type, weight)
+    }
+
+    fn get_neighbors(&self, node: Uuid) -> Result<Vec<(Uuid, f32)>> {
+        Ok(self.fresh()?.forward.get(&node)
+            .map(|edges| edges.iter().map(|e| (e.target_id, e.weight)).collect())
+            .unwrap_or_default())
+    }
+
+    fn get_inbound_neighbors(&self, node: Uuid) -> Result<Vec<(Uuid, f32)>> {
+        Ok(self.fresh()?.reverse.get(&node)
+            .map(|edges| edges.iter().map(|e| (e.source_id, e.weight)).collect())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::centrality::{self, PageRankConfig};
+    use crate::graph::suggest::{self, LinkSuggestion};
+    use crate::graph::testing::unembedded;
+
+    /// Every cached adjacency list matches the store's, edge for edge.
+    fn assert_matches_store(cache: &AdjacencyCache, storage: &StorageManager, ids: &[Uuid]) {
+        let reader = storage.read().unwrap();
+        for id in ids {
+            let cached: Vec<_> = cache.edges_out(*id).unwrap().into_iter().map(|e| (e.target_id, e.relation_type, e.created_at)).collect();
+            let stored: Vec<_> = reader.edges_out(*id).unwrap().into_iter().map(|e| (e.target_id, e.relation_type, e.created_at)).collect();
+            assert_eq!(cached, stored, "edges out of {id}");
+            let cached: Vec<_> = cache.edges_in(*id).unwrap().into_iter().map(|e| (e.source_id, e.relation_type)).collect();
+            let stored: Vec<_> = reader.edges_in(*id).unwrap().into_iter().map(|e| (e.source_id, e.relation_type)).collect();
+            assert_eq!(cached, stored, "edges into {id}");
+        }
+        assert_eq!(cache.revision(), storage.graph_revision().unwrap());
+    }
+
+    #[test]
+    fn stays_in_step_with_writes_through_and_around_it() {
+        let (storage, ids) = unembedded(5, &[(0, 1, "related", 1.0), (1, 2, "related", 0.5)]);
+        let cache = AdjacencyCache::load(storage.shared()).unwrap();
+        assert_eq!(cache.edge_count().unwrap(), 2);
+        assert_matches_store(&cache, &storage, &ids);
+
+        cache.add_edge(ids[2], ids[3], "cites".to_string(), 0.8).unwrap();
+        assert_matches_store(&cache, &storage, &ids);
+
+        // Written on the store behind the cache's back.
+        storage.add_edge_inherent(ids[3], ids[0], "cites".to_string(), 0.7).unwrap();
+        assert_eq!(cache.edges_out(ids[3]).unwrap().len(), 1);
+        assert_matches_store(&cache, &storage, &ids);
+
+        let suggestion = LinkSuggestion {
+            orphan: ids[4],
+            source: ids[4],
+            target: ids[0],
+            relation_type: "relates_to".to_string(),
+            score: 0.9,
+            support: 0.0,
+        };
+        suggest::apply(&cache, &[suggestion], 0.5).unwrap();
+        assert_eq!(cache.edge_count().unwrap(), 5);
+        assert_matches_store(&cache, &storage, &ids);
+
+        assert!(cache.delete_memory(ids[1]).unwrap());
+        assert_eq!(cache.edge_count().unwrap(), 3);
+        assert_matches_store(&cache, &storage, &ids);
+
+        // Algorithms read the same graph through the cache as from a snapshot.
+        let reader = storage.read().unwrap();
+        let config = PageRankConfig::default();
+        let cached = centrality::compute(&reader, &cache, &config, None).unwrap();
+        let stored = centrality::compute(&reader, &reader, &config, None).unwrap();
+        assert_eq!(cached, stored);
+    }
+}
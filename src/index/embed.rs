@@ -0,0 +1,225 @@
+//! Re-embedding a slot with a new model without dumping the store.
+//!
+//! `reembed` walks every memory that has the slot, embeds it in batches
+//! through an `Embedder`, builds a fresh index from the new vectors, and only
+//! then commits all vectors and the slot's new model pin in one redb
+//! transaction. Until that commit, the store and the old index keep serving the
+//! old model; afterwards the returned index is the one to swap in.
+
+use super::{VectorIndex, persist};
+use crate::models::{EmbeddingModel, Memory};
+use crate::storage::StorageManager;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Memories embedded per `Embedder::embed` call.
+const BATCH_SIZE: usize = 64;
+
+/// A source of embeddings, e.g. a local model or a remote API client.
+pub trait Embedder {
+    /// The model every vector from `embed` comes from.
+    fn model(&self) -> EmbeddingModel;
+
+    /// One vector per input text, in order.
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+
+    /// Text embedded for `memory` in `slot`. Defaults to the content; override
+    /// for slots such as `summary` that embed something else.
+    fn text_for<'m>(&self, memory: &'m Memory, slot: &str) -> &'m str {
+        let _ = slot;
+        &memory.content
+    }
+}
+
+/// Re-embeds `slot` with `embedder` and returns the rebuilt index.
+///
+/// `empty` creates the index to fill, as in `persist::load_or_rebuild`; its
+/// dimension may differ from the old one. The snapshot on disk is replaced
+/// as well, so the next `load_or_rebuild` picks up the new index.
+pub fn reembed<V>(storage: &StorageManager, slot: &str, embedder: &dyn Embedder, empty: impl FnOnce() -> V) -> Result<V>
+where
+    V: VectorIndex + Serialize,
+{
+    let model = embedder.model();
+    let memories: Vec<Memory> = storage.list_memories()?
+        .into_iter()
+        .filter(|memory| memory.vector(slot).is_some())
+        .collect();
+
+    let mut vectors = HashMap::with_capacity(memories.len());
+    for batch in memories.chunks(BATCH_SIZE) {
+        let texts: Vec<&str> = batch.iter().map(|memory| embedder.text_for(memory, slot)).collect();
+        let embedded = embedder.embed(&texts)?;
+        if embedded.len() != batch.len() {
+            anyhow::bail!("embedder {model} returned {} vectors for {} texts", embedded.len(), batch.len());
+        }
+        vectors.extend(batch.iter().map(|memory| memory.id).zip(embedded));
+    }
+
+    // Build the index first: a vector it rejects must not reach the store.
+    let mut index = empty();
+    for (id, vector) in &vectors {
+        index.add(*id, vector)?;
+    }
+
+    storage.replace_slot_embeddings(slot, &model, &vectors)?;
+    persist::save(storage, slot, &index)?;
+    tracing::info!(slot, %model, memories = vectors.len(), "re-embedded slot");
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexError;
+    use crate::index::flat::FlatIndex;
+    use crate::index::testing::memory;
+    use crate::models::DEFAULT_SLOT;
+    use crate::storage::TempStorage;
+    use std::cell::Cell;
+
+    /// Embeds `"marker {i}"` as `[1, i, 0]`; `drop_last` loses one vector per
+    /// batch, as a misbehaving client would.
+    struct Stub {
+        model: EmbeddingModel,
+        calls: Cell<usize>,
+        drop_last: bool,
+    }
+
+    impl Stub {
+        fn new(id: &str) -> Self {
+            Self { model: EmbeddingModel::new(id, "v1"), calls: Cell::new(0), drop_last: false }
+        }
+    }
+
+    impl Embedder for Stub {
+        fn model(&self) -> EmbeddingModel {
+            self.model.clone()
+        }
+
+        fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            self.calls.set(self.calls.get() + 1);
+            let mut vectors: Vec<Vec<f32>> = texts.iter().map(|text| marker_vector(text)).collect();
+            if self.drop_last {
+                vectors.pop();
+            }
+            Ok(vectors)
+        }
+    }
+
+    fn marker_vector(text: &str) -> Vec<f32> {
+        let i: f32 = text.trim_start_matches("marker ").parse().unwrap();
+        vec![1.0, i, 0.0]
+    }
+
+    fn marker(i: usize, model: Option<&EmbeddingModel>) -> Memory {
+        let mut memory = memory(vec![i as f32, 1.0]);
+        memory.content = format!("marker {i}");
+        memory.embedding_model = model.cloned();
+        memory
+    }
+
+    #[test]
+    fn reembed_replaces_every_vector_and_the_model_pin() {
+        let storage = TempStorage::new();
+        let old = EmbeddingModel::new("minilm", "v2");
+        let mut memories: Vec<Memory> = (0..BATCH_SIZE + 6).map(|i| marker(i, Some(&old))).collect();
+        memories[0].embeddings.insert("summary".to_string(), vec![0.5, 0.5]);
+        for memory in &memories {
+            storage.save_memory(memory).unwrap();
+        }
+
+        let stub = Stub::new("e5");
+        let index = reembed(&storage, DEFAULT_SLOT, &stub, FlatIndex::new).unwrap();
+        assert_eq!(stub.calls.get(), 2);
+        assert_eq!(index.len(), memories.len());
+        assert_eq!(index.dimension(), Some(3));
+        assert_eq!(storage.slot_model(DEFAULT_SLOT).unwrap(), Some(stub.model.clone()));
+        assert_eq!(storage.slot_dimension(DEFAULT_SLOT).unwrap(), Some(3));
+
+        for memory in &memories {
+            let stored = storage.get_memory(memory.id).unwrap().unwrap();
+            assert_eq!(stored.embedding, marker_vector(&memory.content));
+            assert_eq!(stored.embedding_model, Some(stub.model.clone()));
+            assert!(index.contains(memory.id));
+        }
+        let stored = storage.get_memory(memories[0].id).unwrap().unwrap();
+        assert_eq!(stored.vector("summary"), Some(&[0.5, 0.5][..]));
+        assert_eq!(index.search(&[1.0, 0.0, 0.0], 1).unwrap()[0].0, memories[0].id);
+
+        // The snapshot on disk is the new index.
+        assert!(persist::is_consistent(&storage, DEFAULT_SLOT).unwrap());
+        let loaded = persist::load_or_rebuild(&storage, DEFAULT_SLOT, FlatIndex::new).unwrap();
+        assert_eq!(loaded.search(&[1.0, 5.0, 0.0], 3).unwrap(), index.search(&[1.0, 5.0, 0.0], 3).unwrap());
+    }
+
+    #[test]
+    fn failed_reembed_leaves_the_store_untouched() {
+        let storage = TempStorage::new();
+        let old = EmbeddingModel::new("minilm", "v2");
+        let memories: Vec<Memory> = (0..5).map(|i| marker(i, Some(&old))).collect();
+        for memory in &memories {
+            storage.save_memory(memory).unwrap();
+        }
+
+        let mut stub = Stub::new("e5");
+        stub.drop_last = true;
+        let err = reembed(&storage, DEFAULT_SLOT, &stub, FlatIndex::new).err().expect("embedder dropped a vector");
+        assert!(err.to_string().contains("returned 4 vectors for 5 texts"), "{err}");
+
+        // An index that rejects the new vectors stops the commit too.
+        let err = reembed(&storage, DEFAULT_SLOT, &Stub::new("e5"), || FlatIndex::new().with_dimension(2)).err().expect("index rejects 3-d vectors");
+        assert_eq!(err.downcast::<IndexError>().unwrap(), IndexError::DimensionMismatch { expected: 2, actual: 3 });
+
+        assert_eq!(storage.slot_model(DEFAULT_SLOT).unwrap(), Some(old.clone()));
+        for memory in &memories {
+            let stored = storage.get_memory(memory.id).unwrap().unwrap();
+            assert_eq!(stored.embedding, memory.embedding);
+            assert_eq!(stored.embedding_model, Some(old.clone()));
+        }
+    }
+
+    #[test]
+    fn adopted_slot_can_be_reembedded() {
+        let storage = TempStorage::new();
+        let old = EmbeddingModel::new("minilm", "v2");
+        let mut with_summary: Vec<Memory> = (0..3).map(|i| marker(i, None)).collect();
+        for memory in &mut with_summary {
+            memory.embeddings.insert("summary".to_string(), vec![0.0, 1.0]);
+            storage.save_memory(memory).unwrap();
+        }
+        let without = marker(3, None);
+        storage.save_memory(&without).unwrap();
+
+        // Adopting stamps the slot's vectors and nothing else.
+        storage.adopt_slot_model("summary", &old).unwrap();
+        storage.adopt_slot_model("summary", &old).unwrap();
+        assert_eq!(storage.slot_model("summary").unwrap(), Some(old.clone()));
+        assert_eq!(storage.slot_model(DEFAULT_SLOT).unwrap(), None);
+        for memory in &with_summary {
+            let stored = storage.get_memory(memory.id).unwrap().unwrap();
+            assert_eq!(stored.model("summary"), Some(&old));
+            assert_eq!(stored.model(DEFAULT_SLOT), None);
+        }
+        assert!(storage.get_memory(without.id).unwrap().unwrap().embedding_models.is_empty());
+
+        let stub = Stub::new("e5");
+        let index = reembed(&storage, "summary", &stub, FlatIndex::new).unwrap();
+        assert_eq!(stub.calls.get(), 1);
+        assert_eq!(index.len(), with_summary.len());
+        assert!(!index.contains(without.id));
+        assert_eq!(storage.slot_model("summary").unwrap(), Some(stub.model.clone()));
+
+        // The slot is pinned to the new model now, so the old one is refused.
+        let err = storage.adopt_slot_model("summary", &old).unwrap_err();
+        assert_eq!(
+            err.downcast::<IndexError>().unwrap(),
+            IndexError::ModelMismatch { slot: "summary".to_string(), expected: stub.model.clone(), actual: Some(old) }
+        );
+        let mut unstamped = marker(4, None);
+        unstamped.embeddings.insert("summary".to_string(), vec![1.0, 0.0, 0.0]);
+        let err = storage.save_memory(&unstamped).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(IndexError::ModelMismatch { actual: None, .. })), "{err}");
+    }
+}
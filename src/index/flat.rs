@@ -0,0 +1,365 @@
+//! Exact brute-force index, the ground truth for recall measurements.
+//!
+//! Vectors live in one contiguous row-major buffer (pre-normalized under
+//! `Metric::Cosine`, so cosine becomes a plain dot product), are scored with
+//! the blocked kernels in `metric`, and only the best `k` are kept in a bounded
+//! heap. With `with_threads`, rows are split across scoped threads and the
+//! per-thread heaps merged; results are identical to the single-threaded scan.
+
+use super::persist::{Restore, dimension_matches};
+use super::topk::TopK;
+use super::{IndexError, Metric, VectorIndex, swap_remove_id, swap_remove_row, validate_vector};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use uuid::Uuid;
+
+/// Rows each worker must have before a search is split across threads.
+const MIN_ROWS_PER_THREAD: usize = 4096;
+
+#[derive(Serialize, Deserialize)]
+#[serde(from = "StoredFlat")]
+pub struct FlatIndex {
+    metric: Metric,
+    dimension: Option<usize>,
+    ids: Vec<Uuid>,
+    /// `ids.len() * dimension` values, one row per vector.
+    data: Vec<f32>,
+    #[serde(skip)]
+    positions: HashMap<Uuid, usize>,
+    #[serde(skip)]
+    threads: NonZeroUsize,
+}
+
+#[derive(Deserialize)]
+struct StoredFlat {
+    metric: Metric,
+    dimension: Option<usize>,
+    ids: Vec<Uuid>,
+    data: Vec<f32>,
+}
+
+impl From<StoredFlat> for FlatIndex {
+    fn from(stored: StoredFlat) -> Self {
+        let positions = stored.ids.iter().enumerate().map(|(pos, id)| (*id, pos)).collect();
+        Self {
+            metric: stored.metric,
+            dimension: stored.dimension,
+            ids: stored.ids,
+            data: stored.data,
+            positions,
+            threads: NonZeroUsize::MIN,
+        }
+    }
+}
+
+impl Default for FlatIndex {
+    fn default() -> Self {
+        Self {
+            metric: Metric::default(),
+            dimension: None,
+            ids: Vec::new(),
+            data: Vec::new(),
+            positions: HashMap::new(),
+            threads: NonZeroUsize::MIN,
+        }
+    }
+}
+
+impl FlatIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    /// Scans with up to `threads` workers; `0` uses all available cores.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = NonZeroUsize::new(threads)
+            .or_else(|| std::thread::available_parallelism().ok())
+            .unwrap_or(NonZeroUsize::MIN);
+        self
+    }
+
+    fn row(&self, pos: usize) -> &[f32] {
+        let dim = self.dimension.unwrap_or(0);
+        &self.data[pos * dim..(pos + 1) * dim]
+    }
+
+    /// Scores rows `start..end` against every query, one heap per query.
+    fn scan_batch(&self, queries: &[Vec<f32>], k: usize, start: usize, end: usize) -> Vec<TopK> {
+        let mut tops: Vec<TopK> = queries.iter().map(|_| TopK::new(k)).collect();
+        for pos in start..end {
+            let row = self.row(pos);
+            for (top, query) in tops.iter_mut().zip(queries) {
+                top.push(self.ids[pos], self.metric.score_prepared(query, row));
+            }
+        }
+        tops
+    }
+
+    /// Scores rows `start..end` into a heap of size `k`.
+    fn scan(&self, query: &[f32], k: usize, start: usize, end: usize, mask: Option<&[bool]>) -> TopK {
+        let mut top = TopK::new(k);
+        for pos in start..end {
+            if mask.is_some_and(|m| !m[pos]) {
+                continue;
+            }
+            top.push(self.ids[pos], self.metric.score_prepared(query, self.row(pos)));
+        }
+        top
+    }
+}
+
+impl VectorIndex for FlatIndex {
+    fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.positions.contains_key(&id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, self.dimension)?;
+        self.dimension = Some(vector.len());
+        let row = self.metric.prepare(vector);
+        self.positions.insert(id, self.ids.len());
+        self.ids.push(id);
+        self.data.extend(row);
+        Ok(())
+    }
+
+    fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, self.dimension)?;
+        let query = self.metric.prepare(vector);
+        let rows = self.ids.len();
+        let workers = self.threads.get().min(rows / MIN_ROWS_PER_THREAD).max(1);
+
+        if workers == 1 {
+            let mut top = TopK::new(k);
+            for (pos, id) in self.ids.iter().enumerate() {
+                if allowed(*id) {
+                    top.push(*id, self.metric.score_prepared(&query, self.row(pos)));
+                }
+            }
+            return Ok(top.into_sorted_vec());
+        }
+
+        // The predicate need not be `Sync`, so it is evaluated up front.
+        let mask: Vec<bool> = self.ids.iter().map(|id| allowed(*id)).collect();
+        let chunk = rows.div_ceil(workers);
+        let mut top = TopK::new(k);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|w| {
+                    let (query, mask) = (&query, &mask);
+                    let start = w * chunk;
+                    let end = ((w + 1) * chunk).min(rows);
+                    scope.spawn(move || self.scan(query, k, start, end, Some(mask)))
+                })
+                .collect();
+            for handle in handles {
+                top.merge(handle.join().expect("flat index scan thread panicked"));
+            }
+        });
+        Ok(top.into_sorted_vec())
+    }
+
+    fn search_batch(&self, vectors: &[Vec<f32>], k: usize) -> Result<Vec<Vec<(Uuid, f32)>>> {
+        let mut queries = Vec::with_capacity(vectors.len());
+        for vector in vectors {
+            validate_vector(vector, self.dimension)?;
+            queries.push(self.metric.prepare(vector));
+        }
+
+        // Each row is loaded once and scored against every query while it is hot.
+        let rows = self.ids.len();
+        let workers = self.threads.get().min(rows / MIN_ROWS_PER_THREAD).max(1);
+        if workers == 1 {
+            let tops = self.scan_batch(&queries, k, 0, rows);
+            return Ok(tops.into_iter().map(TopK::into_sorted_vec).collect());
+        }
+
+        let chunk = rows.div_ceil(workers);
+        let mut tops: Vec<TopK> = queries.iter().map(|_| TopK::new(k)).collect();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|w| {
+                    let queries = &queries;
+                    let start = w * chunk;
+                    let end = ((w + 1) * chunk).min(rows);
+                    scope.spawn(move || self.scan_batch(queries, k, start, end))
+                })
+                .collect();
+            for handle in handles {
+                let partial = handle.join().expect("flat index scan thread panicked");
+                for (top, part) in tops.iter_mut().zip(partial) {
+                    top.merge(part);
+                }
+            }
+        });
+        Ok(tops.into_iter().map(TopK::into_sorted_vec).collect())
+    }
+
+    fn search_range_filtered(&self, vector: &[f32], min_score: f32, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, self.dimension)?;
+        let query = self.metric.prepare(vector);
+        let mut hits: Vec<(Uuid, f32)> = self.ids.iter()
+            .enumerate()
+            .filter(|(_, id)| allowed(**id))
+            .map(|(pos, id)| (*id, self.metric.score_prepared(&query, self.row(pos))))
+            .filter(|(_, score)| *score >= min_score)
+            .collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(hits)
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        let Some(pos) = swap_remove_id(&mut self.ids, &mut self.positions, id) else {
+            return Ok(false);
+        };
+        swap_remove_row(&mut self.data, self.dimension.unwrap_or(0), pos);
+        Ok(true)
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn upsert(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        let Some(&pos) = self.positions.get(&id) else {
+            return self.add(id, vector);
+        };
+        validate_vector(vector, self.dimension)?;
+        let row = self.metric.prepare(vector);
+        let dim = row.len();
+        self.data[pos * dim..(pos + 1) * dim].copy_from_slice(&row);
+        Ok(())
+    }
+}
+
+impl Restore for FlatIndex {
+    fn same_config(&self, requested: &Self) -> bool {
+        self.metric == requested.metric && dimension_matches(self.dimension, requested.dimension)
+    }
+
+    fn restore_runtime(&mut self, requested: Self) {
+        self.threads = requested.threads;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::testing::{check_remove_and_upsert, random_vectors};
+
+    /// Every row scored the way the index scores it, best first, equal
+    /// scores larger id first.
+    fn brute_force(metric: Metric, ids: &[Uuid], vectors: &[Vec<f32>], query: &[f32], k: usize, allowed: impl Fn(Uuid) -> bool) -> Vec<(Uuid, f32)> {
+        let query = metric.prepare(query);
+        let mut scored: Vec<(Uuid, f32)> = ids.iter()
+            .zip(vectors)
+            .filter(|(id, _)| allowed(**id))
+            .map(|(id, vector)| (*id, metric.score_prepared(&query, &metric.prepare(vector))))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+        scored.truncate(k);
+        scored
+    }
+
+    #[test]
+    fn threaded_scans_match_single_thread_and_brute_force() {
+        // Enough rows for several workers; every vector appears three times,
+        // so most scores tie.
+        let distinct = random_vectors(MIN_ROWS_PER_THREAD, 8, 21);
+        let vectors: Vec<Vec<f32>> = distinct.iter().cycle().take(3 * MIN_ROWS_PER_THREAD + 5).cloned().collect();
+        let ids: Vec<Uuid> = vectors.iter().map(|_| Uuid::now_v7()).collect();
+        let queries = random_vectors(4, 8, 22);
+
+        for metric in [Metric::Cosine, Metric::Euclidean] {
+            let mut single = FlatIndex::new().with_metric(metric);
+            let mut threaded = FlatIndex::new().with_metric(metric).with_threads(3);
+            for (id, vector) in ids.iter().zip(&vectors) {
+                single.add(*id, vector).unwrap();
+                threaded.add(*id, vector).unwrap();
+            }
+            let even: std::collections::HashSet<Uuid> = ids.iter().step_by(2).copied().collect();
+
+            for k in [1, 10, ids.len() + 7] {
+                let batch = threaded.search_batch(&queries, k).unwrap();
+                assert_eq!(batch, single.search_batch(&queries, k).unwrap());
+                for (query, batched) in queries.iter().zip(&batch) {
+                    let expected = brute_force(metric, &ids, &vectors, query, k, |_| true);
+                    assert_eq!(expected.len(), k.min(ids.len()));
+                    assert_eq!(single.search(query, k).unwrap(), expected);
+                    assert_eq!(threaded.search(query, k).unwrap(), expected);
+                    assert_eq!(batched, &expected);
+
+                    let expected = brute_force(metric, &ids, &vectors, query, k, |id| even.contains(&id));
+                    assert_eq!(single.search_filtered(query, k, &|id| even.contains(&id)).unwrap(), expected);
+                    assert_eq!(threaded.search_filtered(query, k, &|id| even.contains(&id)).unwrap(), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn search_batch_answers_each_query_like_search() {
+        let vectors = random_vectors(300, 8, 23);
+        let ids: Vec<Uuid> = vectors.iter().map(|_| Uuid::now_v7()).collect();
+        let queries = random_vectors(5, 8, 24);
+        for metric in [Metric::Cosine, Metric::DotProduct, Metric::Euclidean, Metric::Manhattan] {
+            let mut index = FlatIndex::new().with_metric(metric);
+            for (id, vector) in ids.iter().zip(&vectors) {
+                index.add(*id, vector).unwrap();
+            }
+            for k in [1, 7, 300] {
+                let batch = index.search_batch(&queries, k).unwrap();
+                assert_eq!(batch.len(), queries.len());
+                for (query, batched) in queries.iter().zip(&batch) {
+                    assert_eq!(batched, &index.search(query, k).unwrap(), "{metric:?} k={k}");
+                }
+            }
+            assert!(index.search_batch(&[], 5).unwrap().is_empty());
+            // One malformed query fails the whole batch.
+            let err = index.search_batch(&[queries[0].clone(), vec![1.0, 0.0]], 5).unwrap_err();
+            assert_eq!(err.downcast::<IndexError>().unwrap(), IndexError::DimensionMismatch { expected: 8, actual: 2 });
+        }
+    }
+
+    #[test]
+    fn empty_index_and_zero_k_return_nothing() {
+        let mut index = FlatIndex::new().with_dimension(2);
+        assert!(index.search(&[1.0, 0.0], 5).unwrap().is_empty());
+        index.add(Uuid::now_v7(), &[1.0, 0.0]).unwrap();
+        assert!(index.search(&[1.0, 0.0], 0).unwrap().is_empty());
+        assert_eq!(index.search_batch(&[vec![1.0, 0.0], vec![0.0, 1.0]], 0).unwrap(), [vec![], vec![]]);
+    }
+
+    #[test]
+    fn remove_and_upsert_keep_positions() {
+        check_remove_and_upsert(&mut FlatIndex::new(), &random_vectors(7, 16, 3));
+        check_remove_and_upsert(&mut FlatIndex::new().with_metric(Metric::Euclidean), &random_vectors(7, 16, 4));
+    }
+}
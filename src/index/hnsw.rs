@@ -0,0 +1,578 @@
+//! Hierarchical Navigable Small World index, with an optional graph-aware mode.
+//!
+//! With `graph_bias = 0` this is plain HNSW. With a positive bias and a
+//! `GraphIndex` attached, neighbour selection during insert ranks candidates
+//! by `similarity + graph_bias * edge_weight`, where `edge_weight` is the
+//! weight of an existing `edges_out`/`edges_in` link between the two memories
+//! (0 if unlinked), and linked memories are always considered as candidates.
+//! The bias only shapes construction: search scores are plain similarities,
+//! so the two modes can be compared on recall and latency directly.
+//!
+//! Edges must exist before a memory is inserted to influence its links, so
+//! build (or `persist::rebuild`) the index after the graph is loaded.
+
+use super::persist::{Restore, dimension_matches};
+use super::topk::TopK;
+use super::{GraphIndex, IndexError, Metric, VectorIndex, validate_vector};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HnswConfig {
+    /// Links per node on upper layers; layer 0 keeps `2 * m`.
+    pub m: usize,
+    /// Candidate list size while inserting.
+    pub ef_construction: usize,
+    /// Candidate list size while searching (raised to `k` if smaller).
+    pub ef_search: usize,
+    pub metric: Metric,
+    /// Weight of existing graph edges in neighbour selection; 0 disables it.
+    pub graph_bias: f32,
+    /// Seed for level assignment, so builds are reproducible.
+    pub seed: u64,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 50,
+            metric: Metric::Cosine,
+            graph_bias: 0.0,
+            seed: 0x5eed_cafe,
+        }
+    }
+}
+
+impl HnswConfig {
+    /// Whether a graph built with `self` is what `other` would build. Only
+    /// `ef_search` may differ, as it affects queries alone.
+    pub fn same_graph(&self, other: &HnswConfig) -> bool {
+        self.m == other.m
+            && self.ef_construction == other.ef_construction
+            && self.metric == other.metric
+            && self.graph_bias == other.graph_bias
+            && self.seed == other.seed
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Node {
+    id: Uuid,
+    vector: Vec<f32>,
+    /// `neighbors[l]` holds this node's links on layer `l`.
+    neighbors: Vec<Vec<u32>>,
+    /// Removed nodes stay in the graph for routing but are never returned.
+    deleted: bool,
+}
+
+impl Node {
+    fn level(&self) -> usize {
+        self.neighbors.len() - 1
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(from = "StoredHnsw")]
+pub struct HnswIndex {
+    config: HnswConfig,
+    dimension: Option<usize>,
+    nodes: Vec<Node>,
+    entry: Option<u32>,
+    rng: u64,
+    #[serde(skip)]
+    positions: HashMap<Uuid, u32>,
+    #[serde(skip)]
+    graph: Option<Arc<dyn GraphIndex + Send + Sync>>,
+}
+
+#[derive(Deserialize)]
+struct StoredHnsw {
+    config: HnswConfig,
+    dimension: Option<usize>,
+    nodes: Vec<Node>,
+    entry: Option<u32>,
+    rng: u64,
+}
+
+impl From<StoredHnsw> for HnswIndex {
+    fn from(stored: StoredHnsw) -> Self {
+        let positions = stored.nodes.iter()
+            .enumerate()
+            .filter(|(_, node)| !node.deleted)
+            .map(|(pos, node)| (node.id, pos as u32))
+            .collect();
+        Self {
+            config: stored.config,
+            dimension: stored.dimension,
+            nodes: stored.nodes,
+            entry: stored.entry,
+            rng: stored.rng,
+            positions,
+            graph: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Candidate {
+    pub(super) score: f32,
+    pub(super) node: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswConfig::default())
+    }
+}
+
+/// Read access to a layered proximity graph, so the search routines below
+/// serve both `HnswIndex` and the memory-mapped `MmapIndex`.
+pub(super) trait Layers {
+    fn similarity(&self, query: &[f32], node: u32) -> f32;
+    fn links(&self, node: u32, level: usize) -> &[u32];
+    fn top_level(&self, node: u32) -> usize;
+}
+
+/// Best-first search on one layer, returning up to `ef` nodes, best first.
+pub(super) fn search_layer(graph: &impl Layers, query: &[f32], entry: &[u32], ef: usize, level: usize) -> Vec<Candidate> {
+    let mut visited: HashSet<u32> = entry.iter().copied().collect();
+    let mut candidates: BinaryHeap<Candidate> = BinaryHeap::new();
+    let mut results: BinaryHeap<Reverse<Candidate>> = BinaryHeap::new();
+
+    for &node in entry {
+        let c = Candidate { score: graph.similarity(query, node), node };
+        candidates.push(c);
+        results.push(Reverse(c));
+    }
+    while results.len() > ef {
+        results.pop();
+    }
+
+    while let Some(current) = candidates.pop() {
+        let worst = results.peek().map_or(f32::NEG_INFINITY, |r| r.0.score);
+        if current.score < worst && results.len() >= ef {
+            break;
+        }
+        for &next in graph.links(current.node, level) {
+            if !visited.insert(next) {
+                continue;
+            }
+            let score = graph.similarity(query, next);
+            let worst = results.peek().map_or(f32::NEG_INFINITY, |r| r.0.score);
+            if results.len() < ef || score > worst {
+                let c = Candidate { score, node: next };
+                candidates.push(c);
+                results.push(Reverse(c));
+                if results.len() > ef {
+                    results.pop();
+                }
+            }
+        }
+    }
+
+    let mut out: Vec<Candidate> = results.into_iter().map(|Reverse(c)| c).collect();
+    out.sort_by(|a, b| b.cmp(a));
+    out
+}
+
+/// Walks down from `entry` to `to_level + 1` and returns the closest node.
+pub(super) fn descend(graph: &impl Layers, query: &[f32], entry: Option<u32>, to_level: usize) -> Option<u32> {
+    let mut current = entry?;
+    let top = graph.top_level(current);
+    for level in (to_level + 1..=top).rev() {
+        if let Some(best) = search_layer(graph, query, &[current], 1, level).first() {
+            current = best.node;
+        }
+    }
+    Some(current)
+}
+
+impl Layers for HnswIndex {
+    fn similarity(&self, query: &[f32], node: u32) -> f32 {
+        self.config.metric.score_prepared(query, &self.nodes[node as usize].vector)
+    }
+
+    fn links(&self, node: u32, level: usize) -> &[u32] {
+        &self.nodes[node as usize].neighbors[level]
+    }
+
+    fn top_level(&self, node: u32) -> usize {
+        self.nodes[node as usize].level()
+    }
+}
+
+impl HnswIndex {
+    pub fn new(config: HnswConfig) -> Self {
+        Self {
+            config,
+            dimension: None,
+            nodes: Vec::new(),
+            entry: None,
+            rng: config.seed,
+            positions: HashMap::new(),
+            graph: None,
+        }
+    }
+
+    /// HNSW whose insert-time neighbour selection favours memories linked in `graph`.
+    pub fn graph_aware(config: HnswConfig, graph: Arc<dyn GraphIndex + Send + Sync>, graph_bias: f32) -> Self {
+        Self::new(HnswConfig { graph_bias, ..config }).with_graph(graph)
+    }
+
+    /// Attaches the edge source consulted on insert, e.g. after loading a snapshot.
+    pub fn with_graph(mut self, graph: Arc<dyn GraphIndex + Send + Sync>) -> Self {
+        self.graph = Some(graph);
+        self
+    }
+
+    pub fn with_dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    pub fn config(&self) -> &HnswConfig {
+        &self.config
+    }
+
+    pub(super) fn entry_point(&self) -> Option<u32> {
+        self.entry
+    }
+
+    /// Nodes in insertion order as `(id, prepared vector, links per layer,
+    /// deleted)`, for writing the graph to disk (see `mmap`).
+    pub(super) fn raw_nodes(&self) -> impl Iterator<Item = (Uuid, &[f32], &[Vec<u32>], bool)> {
+        self.nodes.iter().map(|node| (node.id, node.vector.as_slice(), node.neighbors.as_slice(), node.deleted))
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 { self.config.m * 2 } else { self.config.m }
+    }
+
+    /// Draws a level from the usual `floor(-ln(U) / ln(m))` distribution.
+    fn random_level(&mut self) -> usize {
+        // SplitMix64
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let ml = 1.0 / (self.config.m.max(2) as f64).ln();
+        (-uniform.ln() * ml).floor() as usize
+    }
+
+    /// Existing graph links of `id`, keyed by index node, with the strongest edge weight.
+    fn graph_links(&self, id: Uuid) -> Result<HashMap<u32, f32>> {
+        let mut links = HashMap::new();
+        let Some(graph) = self.graph.as_ref().filter(|_| self.config.graph_bias != 0.0) else {
+            return Ok(links);
+        };
+        let neighbors = graph.get_neighbors(id)?.into_iter().chain(graph.get_inbound_neighbors(id)?);
+        for (other, weight) in neighbors {
+            if let Some(&node) = self.positions.get(&other) {
+                let entry = links.entry(node).or_insert(weight);
+                *entry = entry.max(weight);
+            }
+        }
+        Ok(links)
+    }
+
+    fn biased(&self, candidate: Candidate, links: &HashMap<u32, f32>) -> f32 {
+        candidate.score + self.config.graph_bias * links.get(&candidate.node).copied().unwrap_or(0.0)
+    }
+
+    /// Trims `node`'s links on `level` to the best `max_links`, ranked like
+    /// insert-time selection so graph-linked neighbours keep their bias.
+    /// `links` are `node`'s own graph links, as returned by `graph_links`.
+    fn prune(&mut self, node: u32, level: usize, links: &HashMap<u32, f32>) {
+        let max = self.max_links(level);
+        let base = self.nodes[node as usize].vector.clone();
+        let mut scored: Vec<(f32, u32)> = self.nodes[node as usize].neighbors[level].iter()
+            .map(|&n| (self.biased(Candidate { score: self.similarity(&base, n), node: n }, links), n))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(max);
+        self.nodes[node as usize].neighbors[level] = scored.into_iter().map(|(_, n)| n).collect();
+    }
+}
+
+impl VectorIndex for HnswIndex {
+    fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.positions.contains_key(&id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, self.dimension)?;
+        self.dimension = Some(vector.len());
+
+        let query = self.config.metric.prepare(vector);
+        let links = self.graph_links(id)?;
+        let level = self.random_level();
+        let node = self.nodes.len() as u32;
+
+        let Some(entry) = self.entry else {
+            self.nodes.push(Node { id, vector: query, neighbors: vec![Vec::new(); level + 1], deleted: false });
+            self.positions.insert(id, node);
+            self.entry = Some(node);
+            return Ok(());
+        };
+
+        let top = self.nodes[entry as usize].level();
+        let mut entry_points = vec![descend(self, &query, self.entry, level).unwrap_or(entry)];
+        let mut neighbors = vec![Vec::new(); level + 1];
+
+        for l in (0..=level.min(top)).rev() {
+            let mut candidates = search_layer(self, &query, &entry_points, self.config.ef_construction, l);
+            entry_points = candidates.iter().map(|c| c.node).collect();
+
+            // Linked memories present on this layer compete even if the
+            // similarity search did not reach them.
+            let seen: HashSet<u32> = entry_points.iter().copied().collect();
+            for &linked in links.keys() {
+                if !seen.contains(&linked) && self.nodes[linked as usize].level() >= l {
+                    candidates.push(Candidate { score: self.similarity(&query, linked), node: linked });
+                }
+            }
+
+            candidates.sort_by(|a, b| self.biased(*b, &links).total_cmp(&self.biased(*a, &links)));
+            candidates.truncate(self.max_links(l));
+            neighbors[l] = candidates.iter().map(|c| c.node).collect();
+        }
+
+        self.nodes.push(Node { id, vector: query, neighbors, deleted: false });
+        self.positions.insert(id, node);
+
+        // A neighbour can overflow on several layers; read its graph links
+        // once per insert rather than once per prune.
+        let mut neighbor_links: HashMap<u32, HashMap<u32, f32>> = HashMap::new();
+        for l in 0..=level.min(top) {
+            for n in self.nodes[node as usize].neighbors[l].clone() {
+                self.nodes[n as usize].neighbors[l].push(node);
+                if self.nodes[n as usize].neighbors[l].len() <= self.max_links(l) {
+                    continue;
+                }
+                let links = match neighbor_links.entry(n) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(self.graph_links(self.nodes[n as usize].id)?),
+                };
+                self.prune(n, l, links);
+            }
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+        Ok(())
+    }
+
+    fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, self.dimension)?;
+        let query = self.config.metric.prepare(vector);
+        let Some(entry) = descend(self, &query, self.entry, 0) else {
+            return Ok(Vec::new());
+        };
+
+        let ef = self.config.ef_search.max(k);
+        let mut top = TopK::new(k);
+        let mut found = 0;
+        for c in search_layer(self, &query, &[entry], ef, 0) {
+            let node = &self.nodes[c.node as usize];
+            if !node.deleted && allowed(node.id) {
+                top.push(node.id, c.score);
+                found += 1;
+            }
+        }
+
+        // A selective predicate can reject most of the beam; fall back to an
+        // exact scan over the allowed nodes so up to `k` are still returned.
+        if found < k {
+            top = TopK::new(k);
+            for (pos, node) in self.nodes.iter().enumerate() {
+                if !node.deleted && allowed(node.id) {
+                    top.push(node.id, self.similarity(&query, pos as u32));
+                }
+            }
+        }
+        Ok(top.into_sorted_vec())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        let Some(node) = self.positions.remove(&id) else {
+            return Ok(false);
+        };
+        self.nodes[node as usize].deleted = true;
+        Ok(true)
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.config.metric
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+}
+
+impl Restore for HnswIndex {
+    /// Everything that shaped the graph must match; `ef_search` only affects
+    /// queries and is taken from `requested`.
+    fn same_config(&self, requested: &Self) -> bool {
+        self.config.same_graph(&requested.config) && dimension_matches(self.dimension, requested.dimension)
+    }
+
+    fn restore_runtime(&mut self, requested: Self) {
+        self.config.ef_search = requested.config.ef_search;
+        self.graph = requested.graph;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::testing::{ground_truth, random_vectors, recall_at_k};
+
+    /// Undirected edges for the graph-aware mode.
+    #[derive(Default)]
+    struct Links(HashMap<Uuid, Vec<(Uuid, f32)>>);
+
+    impl GraphIndex for Links {
+        fn add_edge(&mut self, source: Uuid, target: Uuid, _relation_type: String, weight: f32) -> Result<()> {
+            self.0.entry(source).or_default().push((target, weight));
+            self.0.entry(target).or_default().push((source, weight));
+            Ok(())
+        }
+
+        fn get_neighbors(&self, node: Uuid) -> Result<Vec<(Uuid, f32)>> {
+            Ok(self.0.get(&node).cloned().unwrap_or_default())
+        }
+
+        fn get_inbound_neighbors(&self, _node: Uuid) -> Result<Vec<(Uuid, f32)>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn build(config: HnswConfig, ids: &[Uuid], vectors: &[Vec<f32>]) -> HnswIndex {
+        let mut index = HnswIndex::new(config);
+        for (id, vector) in ids.iter().zip(vectors) {
+            index.add(*id, vector).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn recall_against_exact_search() {
+        let vectors = random_vectors(2000, 32, 21);
+        let ids: Vec<Uuid> = (0..vectors.len()).map(|_| Uuid::now_v7()).collect();
+        let queries = random_vectors(50, 32, 22);
+        let exact = ground_truth(Metric::Cosine, &ids, &vectors);
+        let index = build(HnswConfig::default(), &ids, &vectors);
+
+        let recall = recall_at_k(&index, &exact, &queries, 10);
+        assert!(recall >= 0.9, "HNSW recall@10 {recall}");
+    }
+
+    #[test]
+    fn removed_memories_are_tombstoned() {
+        let vectors = random_vectors(200, 8, 31);
+        let ids: Vec<Uuid> = (0..vectors.len()).map(|_| Uuid::now_v7()).collect();
+        let mut index = build(HnswConfig::default(), &ids, &vectors);
+
+        assert!(index.remove(ids[0]).unwrap());
+        assert!(!index.remove(ids[0]).unwrap());
+        assert!(!index.contains(ids[0]));
+        assert_eq!(index.len(), ids.len() - 1);
+        // The node still routes searches but is never returned.
+        assert_eq!(index.nodes.len(), ids.len());
+        let hits = index.search(&vectors[0], 10).unwrap();
+        assert_eq!(hits.len(), 10);
+        assert!(hits.iter().all(|(id, _)| *id != ids[0]));
+
+        index.upsert(ids[0], &vectors[0]).unwrap();
+        assert_eq!(index.search(&vectors[0], 1).unwrap()[0].0, ids[0]);
+    }
+
+    #[test]
+    fn selective_filter_falls_back_to_exact_scan() {
+        let vectors = random_vectors(500, 16, 41);
+        let ids: Vec<Uuid> = (0..vectors.len()).map(|_| Uuid::now_v7()).collect();
+        let index = build(HnswConfig::default(), &ids, &vectors);
+        let exact = ground_truth(Metric::Cosine, &ids, &vectors);
+
+        // Far fewer allowed memories than the beam keeps.
+        let allowed: HashSet<Uuid> = ids.iter().step_by(50).copied().collect();
+        let filter = |id: Uuid| allowed.contains(&id);
+        let query = &vectors[1];
+        let hits = index.search_filtered(query, 5, &filter).unwrap();
+        assert_eq!(hits, exact.search_filtered(query, 5, &filter).unwrap());
+
+        let hits = index.search_filtered(query, 50, &filter).unwrap();
+        assert_eq!(hits.len(), allowed.len());
+    }
+
+    #[test]
+    fn graph_bias_links_connected_memories() {
+        // Two dissimilar memories joined by an edge, among many closer ones.
+        let mut vectors = random_vectors(300, 8, 51);
+        vectors[0] = vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        vectors[1] = vec![-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let ids: Vec<Uuid> = (0..vectors.len()).map(|_| Uuid::now_v7()).collect();
+        let mut links = Links::default();
+        links.add_edge(ids[0], ids[1], "relates_to".to_string(), 1.0).unwrap();
+        let links = Arc::new(links);
+
+        let linked = |index: &HnswIndex| {
+            let (a, b) = (index.positions[&ids[0]], index.positions[&ids[1]]);
+            index.nodes[b as usize].neighbors[0].contains(&a)
+        };
+        let plain = build(HnswConfig::default(), &ids, &vectors);
+        assert!(!linked(&plain));
+
+        let mut aware = HnswIndex::graph_aware(HnswConfig::default(), links, 4.0);
+        for (id, vector) in ids.iter().zip(&vectors) {
+            aware.add(*id, vector).unwrap();
+        }
+        assert!(linked(&aware));
+
+        // The bias shapes links only; scores are still plain similarities.
+        let hits = aware.search(&vectors[0], 1).unwrap();
+        assert_eq!(hits[0].0, ids[0]);
+        assert!((hits[0].1 - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,351 @@
+//! Coarse-to-fine search for Matryoshka embeddings.
+//!
+//! Models trained with Matryoshka representation learning pack the most
+//! information into the leading dimensions, so the first `prefix_len` values
+//! of each vector are a usable embedding on their own. `MatryoshkaIndex`
+//! scans only those prefixes to pick `k * oversample` candidates, then
+//! re-scores the candidates with the full vectors. With `with_rerank`, full
+//! vectors are not kept in memory at all and are read from redb instead.
+
+use super::persist::{Restore, dimension_matches};
+use super::quantized::Rerank;
+use super::topk::TopK;
+use super::{IndexError, Metric, VectorIndex, swap_remove_id, swap_remove_row, validate_vector};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const DEFAULT_OVERSAMPLE: usize = 4;
+
+#[derive(Serialize, Deserialize)]
+#[serde(from = "StoredMatryoshka")]
+pub struct MatryoshkaIndex {
+    metric: Metric,
+    dimension: Option<usize>,
+    prefix_len: usize,
+    oversample: usize,
+    ids: Vec<Uuid>,
+    /// `ids.len() * prefix_len` values; each prefix is prepared on its own, so
+    /// under cosine it is re-normalized rather than a slice of the full row.
+    prefixes: Vec<f32>,
+    /// `ids.len() * dimension` prepared full rows, empty when re-ranking
+    /// against redb.
+    full: Vec<f32>,
+    #[serde(skip)]
+    positions: HashMap<Uuid, usize>,
+    #[serde(skip)]
+    rerank: Option<Rerank>,
+}
+
+#[derive(Deserialize)]
+struct StoredMatryoshka {
+    metric: Metric,
+    dimension: Option<usize>,
+    prefix_len: usize,
+    oversample: usize,
+    ids: Vec<Uuid>,
+    prefixes: Vec<f32>,
+    full: Vec<f32>,
+}
+
+impl From<StoredMatryoshka> for MatryoshkaIndex {
+    fn from(stored: StoredMatryoshka) -> Self {
+        let positions = stored.ids.iter().enumerate().map(|(pos, id)| (*id, pos)).collect();
+        Self {
+            metric: stored.metric,
+            dimension: stored.dimension,
+            prefix_len: stored.prefix_len,
+            oversample: stored.oversample,
+            ids: stored.ids,
+            prefixes: stored.prefixes,
+            full: stored.full,
+            positions,
+            rerank: None,
+        }
+    }
+}
+
+impl MatryoshkaIndex {
+    /// Searches the first `prefix_len` dimensions, e.g. 128 of a 768-d vector.
+    pub fn new(prefix_len: usize) -> Self {
+        Self {
+            metric: Metric::default(),
+            dimension: None,
+            prefix_len: prefix_len.max(1),
+            oversample: DEFAULT_OVERSAMPLE,
+            ids: Vec::new(),
+            prefixes: Vec::new(),
+            full: Vec::new(),
+            positions: HashMap::new(),
+            rerank: None,
+        }
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    /// Prefix candidates fetched per requested result before re-scoring.
+    pub fn with_oversample(mut self, oversample: usize) -> Self {
+        self.oversample = oversample.max(1);
+        self
+    }
+
+    /// Re-scores against the embeddings in redb and drops the in-memory full
+    /// rows, leaving only the prefixes resident. The rerank's own
+    /// `oversample` replaces `with_oversample`.
+    pub fn with_rerank(mut self, rerank: Rerank) -> Self {
+        self.full = Vec::new();
+        self.rerank = Some(rerank);
+        self
+    }
+
+    pub fn prefix_len(&self) -> usize {
+        self.prefix_len
+    }
+
+    fn prefix_row(&self, pos: usize) -> &[f32] {
+        &self.prefixes[pos * self.prefix_len..(pos + 1) * self.prefix_len]
+    }
+
+    /// Whether full rows are held for every vector.
+    fn has_full_rows(&self) -> bool {
+        !self.ids.is_empty() && self.full.len() == self.ids.len() * self.dimension.unwrap_or(0)
+    }
+
+    fn full_row(&self, pos: usize) -> &[f32] {
+        let dim = self.dimension.unwrap_or(0);
+        &self.full[pos * dim..(pos + 1) * dim]
+    }
+}
+
+impl VectorIndex for MatryoshkaIndex {
+    fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.positions.contains_key(&id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, self.dimension)?;
+        if vector.len() < self.prefix_len {
+            anyhow::bail!("prefix length {} exceeds embedding dimension {}", self.prefix_len, vector.len());
+        }
+        self.dimension = Some(vector.len());
+        // Only keep full rows while every earlier row is present too, so a
+        // rerank snapshot loaded without its rerank stays prefix-only.
+        if self.rerank.is_none() && self.full.len() == self.ids.len() * vector.len() {
+            self.full.extend(self.metric.prepare(vector));
+        }
+        self.positions.insert(id, self.ids.len());
+        self.ids.push(id);
+        self.prefixes.extend(self.metric.prepare(&vector[..self.prefix_len]));
+        Ok(())
+    }
+
+    fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, self.dimension)?;
+        let fetch = match &self.rerank {
+            Some(rerank) => rerank.candidates(k),
+            None => k.saturating_mul(self.oversample),
+        };
+
+        // Coarse pass over the prefixes only.
+        let prefix = self.metric.prepare(&vector[..self.prefix_len.min(vector.len())]);
+        let mut coarse = TopK::new(fetch);
+        coarse.extend(self.ids.iter()
+            .enumerate()
+            .filter(|(_, id)| allowed(**id))
+            .map(|(pos, id)| (*id, self.metric.score_prepared(&prefix, self.prefix_row(pos)))));
+        let candidates = coarse.into_sorted_vec();
+
+        // Fine pass with the full vectors.
+        if let Some(rerank) = &self.rerank {
+            return rerank.apply(self.metric, vector, candidates, k);
+        }
+        // A snapshot saved with `with_rerank` has no full rows; fall back to
+        // the prefix scores until a rerank is attached again.
+        if !self.has_full_rows() {
+            return Ok(candidates.into_iter().take(k).collect());
+        }
+        let query = self.metric.prepare(vector);
+        let mut fine = TopK::new(k);
+        fine.extend(candidates.into_iter()
+            .map(|(id, _)| (id, self.metric.score_prepared(&query, self.full_row(self.positions[&id])))));
+        Ok(fine.into_sorted_vec())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        let full = self.has_full_rows();
+        let Some(pos) = swap_remove_id(&mut self.ids, &mut self.positions, id) else {
+            return Ok(false);
+        };
+        swap_remove_row(&mut self.prefixes, self.prefix_len, pos);
+        if full {
+            swap_remove_row(&mut self.full, self.dimension.unwrap_or(0), pos);
+        }
+        Ok(true)
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+}
+
+impl Restore for MatryoshkaIndex {
+    /// A snapshot saved with `with_rerank` has no full rows, so it only serves
+    /// an index that re-ranks as well.
+    fn same_config(&self, requested: &Self) -> bool {
+        self.metric == requested.metric
+            && self.prefix_len == requested.prefix_len
+            && dimension_matches(self.dimension, requested.dimension)
+            && (requested.rerank.is_some() || self.ids.is_empty() || self.has_full_rows())
+    }
+
+    fn restore_runtime(&mut self, requested: Self) {
+        self.oversample = requested.oversample;
+        if requested.rerank.is_some() {
+            self.full = Vec::new();
+        }
+        self.rerank = requested.rerank;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::VectorSource;
+    use crate::index::testing::{check_remove_and_upsert, ground_truth, random_vectors, recall_at_k};
+    use crate::models::DEFAULT_SLOT;
+    use std::sync::Arc;
+
+    struct Vectors(HashMap<Uuid, Vec<f32>>);
+
+    impl VectorSource for Vectors {
+        fn get_vector(&self, id: Uuid, _slot: &str) -> Result<Option<Vec<f32>>> {
+            Ok(self.0.get(&id).cloned())
+        }
+    }
+
+    /// Random vectors whose leading dimensions carry most of the weight, as
+    /// in a Matryoshka-trained embedding.
+    fn nested_vectors(n: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+        random_vectors(n, dimension, seed).into_iter()
+            .map(|vector| vector.iter().enumerate().map(|(i, x)| x * 0.8f32.powi(i as i32)).collect())
+            .collect()
+    }
+
+    fn build(index: MatryoshkaIndex, ids: &[Uuid], vectors: &[Vec<f32>]) -> MatryoshkaIndex {
+        let mut index = index;
+        for (id, vector) in ids.iter().zip(vectors) {
+            index.add(*id, vector).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn prefix_candidates_are_rescored_with_full_vectors() {
+        let vectors = nested_vectors(1000, 32, 41);
+        let ids: Vec<Uuid> = vectors.iter().map(|_| Uuid::now_v7()).collect();
+        let full: HashMap<Uuid, &Vec<f32>> = ids.iter().copied().zip(&vectors).collect();
+        let queries = nested_vectors(30, 32, 42);
+        let exact = ground_truth(Metric::Cosine, &ids, &vectors);
+        let index = build(MatryoshkaIndex::new(8), &ids, &vectors);
+
+        let recall = recall_at_k(&index, &exact, &queries, 10);
+        assert!(recall >= 0.9, "Matryoshka recall@10 {recall}");
+        for query in &queries {
+            for (id, score) in index.search(query, 10).unwrap() {
+                assert!((score - Metric::Cosine.score(query, full[&id])).abs() < 1e-5);
+            }
+        }
+
+        // Without oversampling the prefix ranking alone decides the top k.
+        let narrow = build(MatryoshkaIndex::new(8).with_oversample(1), &ids, &vectors);
+        assert!(recall_at_k(&narrow, &exact, &queries, 10) < recall);
+    }
+
+    #[test]
+    fn prefix_longer_than_the_embedding_is_rejected() {
+        let mut index = MatryoshkaIndex::new(8);
+        let err = index.add(Uuid::now_v7(), &[1.0, 0.0, 0.0, 0.0]).unwrap_err();
+        assert!(err.to_string().contains("prefix length 8 exceeds embedding dimension 4"), "{err}");
+        assert!(index.is_empty());
+        assert_eq!(index.dimension(), None);
+
+        index.add(Uuid::now_v7(), &[1.0; 8]).unwrap();
+        assert_eq!(index.dimension(), Some(8));
+    }
+
+    #[test]
+    fn remove_and_upsert_keep_positions() {
+        check_remove_and_upsert(&mut MatryoshkaIndex::new(8), &random_vectors(7, 16, 3));
+    }
+
+    #[test]
+    fn removal_keeps_prefix_and_full_rows_aligned() {
+        let vectors = nested_vectors(60, 16, 43);
+        let ids: Vec<Uuid> = vectors.iter().map(|_| Uuid::now_v7()).collect();
+        let mut index = build(MatryoshkaIndex::new(4), &ids, &vectors);
+        // Last, first and middle rows, each moving a different row into the gap.
+        for pos in [59, 0, 30, 17] {
+            assert!(index.remove(ids[pos]).unwrap());
+        }
+
+        let kept: Vec<usize> = (0..60).filter(|pos| ![59, 0, 30, 17].contains(pos)).collect();
+        let kept_ids: Vec<Uuid> = kept.iter().map(|&pos| ids[pos]).collect();
+        let kept_vectors: Vec<Vec<f32>> = kept.iter().map(|&pos| vectors[pos].clone()).collect();
+        let fresh = build(MatryoshkaIndex::new(4), &kept_ids, &kept_vectors);
+        assert_eq!(index.len(), fresh.len());
+        for query in nested_vectors(10, 16, 44) {
+            assert_eq!(index.search(&query, 8).unwrap(), fresh.search(&query, 8).unwrap());
+        }
+    }
+
+    #[test]
+    fn snapshot_without_full_rows_falls_back_to_prefix_scores() {
+        let vectors = nested_vectors(100, 16, 45);
+        let ids: Vec<Uuid> = vectors.iter().map(|_| Uuid::now_v7()).collect();
+        let source = Arc::new(Vectors(ids.iter().copied().zip(vectors.iter().cloned()).collect()));
+        let reranked = build(MatryoshkaIndex::new(4).with_rerank(Rerank::new(source.clone(), DEFAULT_SLOT, 4)), &ids, &vectors);
+        let query = &nested_vectors(1, 16, 46)[0];
+        for (id, score) in reranked.search(query, 5).unwrap() {
+            assert!((score - Metric::Cosine.score(query, &source.0[&id])).abs() < 1e-5);
+        }
+
+        // Loaded without its rerank, the snapshot only has prefixes to score.
+        let mut loaded: MatryoshkaIndex = serde_json::from_slice(&serde_json::to_vec(&reranked).unwrap()).unwrap();
+        assert!(!loaded.same_config(&MatryoshkaIndex::new(4)));
+        let prefix_scores = |index: &MatryoshkaIndex| {
+            for (id, score) in index.search(query, 5).unwrap() {
+                assert!((score - Metric::Cosine.score(&query[..4], &source.0[&id][..4])).abs() < 1e-5);
+            }
+        };
+        prefix_scores(&loaded);
+
+        // Rows added later stay prefix-only rather than misaligning `full`.
+        loaded.add(Uuid::now_v7(), &vectors[0]).unwrap();
+        assert!(!loaded.has_full_rows());
+        prefix_scores(&loaded);
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+/// Distance function an index is built with.
+///
+/// Every metric is reported as a similarity where higher is better, so ranking
+/// and score decay in `QueryEngine` work the same regardless of the choice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Cosine similarity in [-1, 1].
+    #[default]
+    Cosine,
+    /// Raw inner product, for models trained with dot-product objectives.
+    DotProduct,
+    /// L2 distance `d`, reported as `1 / (1 + d)`.
+    Euclidean,
+    /// L1 distance `d`, reported as `1 / (1 + d)`.
+    Manhattan,
+}
+
+impl Metric {
+    /// Similarity of `a` and `b`.
+    ///
+    /// Panics if their lengths differ; vectors from different models must
+    /// never be compared, so callers validate with `validate_vector` first.
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => {
+                let norm_a = norm(a);
+                let norm_b = norm(b);
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    0.0
+                } else {
+                    dot(a, b) / (norm_a * norm_b)
+                }
+            }
+            Metric::DotProduct => dot(a, b),
+            Metric::Euclidean => distance_to_similarity(squared_l2(a, b).sqrt()),
+            Metric::Manhattan => distance_to_similarity(l1(a, b)),
+        }
+    }
+
+    /// Normalizes under cosine, so stored rows can be scored with `score_prepared`.
+    pub(crate) fn prepare(self, vector: &[f32]) -> Vec<f32> {
+        let n = norm(vector);
+        if self == Metric::Cosine && n != 0.0 {
+            vector.iter().map(|x| x / n).collect()
+        } else {
+            vector.to_vec()
+        }
+    }
+
+    /// `score` for two vectors that went through `prepare`.
+    pub(crate) fn score_prepared(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine | Metric::DotProduct => dot(a, b),
+            _ => self.score(a, b),
+        }
+    }
+}
+
+/// Lanes per accumulator block. Eight independent partial sums let the
+/// compiler keep the loop in SIMD registers (AVX on x86-64, 2x NEON on ARM)
+/// without reassociating a single running sum.
+const LANES: usize = 8;
+
+#[inline]
+fn reduce(a: &[f32], b: &[f32], op: impl Fn(f32, f32) -> f32) -> f32 {
+    assert_eq!(a.len(), b.len(), "compared vectors of different dimensions");
+    let mut acc = [0.0f32; LANES];
+    let blocks_a = a.chunks_exact(LANES);
+    let blocks_b = b.chunks_exact(LANES);
+    let tail: f32 = blocks_a.remainder()
+        .iter()
+        .zip(blocks_b.remainder())
+        .map(|(x, y)| op(*x, *y))
+        .sum();
+    for (xa, xb) in blocks_a.zip(blocks_b) {
+        for lane in 0..LANES {
+            acc[lane] += op(xa[lane], xb[lane]);
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    reduce(a, b, |x, y| x * y)
+}
+
+pub(crate) fn squared_l2(a: &[f32], b: &[f32]) -> f32 {
+    reduce(a, b, |x, y| (x - y) * (x - y))
+}
+
+pub(crate) fn l1(a: &[f32], b: &[f32]) -> f32 {
+    reduce(a, b, |x, y| (x - y).abs())
+}
+
+pub(crate) fn norm(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn distance_to_similarity(distance: f32) -> f32 {
+    1.0 / (1.0 + distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: f32, expected: f32) -> bool {
+        (actual - expected).abs() < 1e-6
+    }
+
+    #[test]
+    fn scores_each_metric() {
+        let (a, b) = ([1.0, 2.0, 2.0], [2.0, 0.0, 1.0]);
+        // a.b = 4, |a| = 3, |b| = sqrt(5), |a-b|^2 = 1 + 4 + 1, |a-b|_1 = 1 + 2 + 1
+        assert!(close(Metric::Cosine.score(&a, &b), 4.0 / (3.0 * 5f32.sqrt())));
+        assert!(close(Metric::DotProduct.score(&a, &b), 4.0));
+        assert!(close(Metric::Euclidean.score(&a, &b), 1.0 / (1.0 + 6f32.sqrt())));
+        assert!(close(Metric::Manhattan.score(&a, &b), 1.0 / (1.0 + 4.0)));
+
+        assert!(close(Metric::Cosine.score(&a, &[-2.0, -4.0, -4.0]), -1.0));
+        assert!(close(Metric::DotProduct.score(&a, &[-1.0, 0.0, 0.0]), -1.0));
+    }
+
+    #[test]
+    fn distances_map_to_one_over_one_plus_distance() {
+        assert_eq!(distance_to_similarity(0.0), 1.0);
+        assert_eq!(distance_to_similarity(1.0), 0.5);
+        assert_eq!(distance_to_similarity(3.0), 0.25);
+        let a = [0.5, -1.0];
+        assert_eq!(Metric::Euclidean.score(&a, &a), 1.0);
+        assert_eq!(Metric::Manhattan.score(&a, &a), 1.0);
+        assert!(Metric::Euclidean.score(&a, &[0.5, 1.0]) > Metric::Euclidean.score(&a, &[0.5, 3.0]));
+    }
+
+    #[test]
+    fn zero_vectors_have_no_cosine_similarity() {
+        let zero = [0.0; 4];
+        assert_eq!(Metric::Cosine.score(&zero, &[1.0, 0.0, 0.0, 0.0]), 0.0);
+        assert_eq!(Metric::Cosine.score(&[1.0, 0.0, 0.0, 0.0], &zero), 0.0);
+        assert_eq!(Metric::Cosine.score(&zero, &zero), 0.0);
+        assert_eq!(Metric::Cosine.prepare(&zero), zero);
+    }
+
+    #[test]
+    fn blocked_kernels_match_a_plain_sum() {
+        // Longer than one block of lanes, with a remainder.
+        let a: Vec<f32> = (0..19).map(|i| i as f32 * 0.5 - 3.0).collect();
+        let b: Vec<f32> = (0..19).map(|i| (i as f32).sin()).collect();
+        let plain_dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        let plain_l1: f32 = a.iter().zip(&b).map(|(x, y)| (x - y).abs()).sum();
+        assert!((dot(&a, &b) - plain_dot).abs() < 1e-4);
+        assert!((l1(&a, &b) - plain_l1).abs() < 1e-4);
+
+        let prepared = (Metric::Cosine.prepare(&a), Metric::Cosine.prepare(&b));
+        assert!(close(Metric::Cosine.score_prepared(&prepared.0, &prepared.1), Metric::Cosine.score(&a, &b)));
+    }
+
+    #[test]
+    #[should_panic(expected = "different dimensions")]
+    fn mismatched_lengths_panic() {
+        Metric::DotProduct.score(&[1.0, 2.0], &[1.0]);
+    }
+}
@@ -0,0 +1,499 @@
+//! HNSW index served straight from a memory-mapped file.
+//!
+//! The file sits next to the redb database (`<db>.vmm`, or `<db>.<slot>.vmm`
+//! for named slots) and holds the ids, prepared vectors and layered links of
+//! an `HnswIndex`. Opening it maps the file and indexes the ids, so restart
+//! costs milliseconds and the vectors are paged in by the OS on demand instead
+//! of being loaded into the heap.
+//!
+//! The mapping is read-only. Vectors added after opening go to a small
+//! in-heap delta that is scanned exactly, and removals are tombstoned;
+//! `compact` folds both back into a fresh file. Like `persist`, the file
+//! carries an `IndexMarker`, and `open_or_build` rebuilds it from
+//! `list_memories` when the store has moved on, was indexed with another HNSW
+//! configuration or dimension, or the file fails `open`'s bounds checks.
+//!
+//! Layout, little-endian, each section 16-byte aligned:
+//!
+//! ```text
+//! "MGVMMAP1" | header length: u32 | JSON header | ids: [u8; 16] per node
+//! | vectors: f32 | deleted: u8 per node | list_start: u32 per node + 1
+//! | link_start: u32 per list + 1 | links: u32
+//! ```
+//!
+//! Node `n`'s links on layer `l` are
+//! `links[link_start[list_start[n] + l]..link_start[list_start[n] + l + 1]]`.
+
+use super::hnsw::{HnswConfig, HnswIndex, Layers, descend, search_layer};
+use super::persist::IndexMarker;
+use super::topk::TopK;
+use super::{IndexError, Metric, VectorIndex, validate_vector};
+use crate::models::DEFAULT_SLOT;
+use crate::storage::StorageManager;
+use anyhow::Result;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const MAGIC: &[u8; 8] = b"MGVMMAP1";
+const ALIGN: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Section {
+    offset: usize,
+    len: usize,
+}
+
+/// `section` of `map` as `T`s, or an error if it lies outside the map or is
+/// misaligned or not a whole number of `T`s.
+fn cast<T: bytemuck::Pod>(map: &[u8], section: Section) -> Result<&[T]> {
+    let bytes = section.offset.checked_add(section.len)
+        .and_then(|end| map.get(section.offset..end))
+        .ok_or_else(|| anyhow::anyhow!("section {section:?} runs past the end of the file"))?;
+    bytemuck::try_cast_slice(bytes).map_err(|err| anyhow::anyhow!("section {section:?}: {err}"))
+}
+
+impl Header {
+    /// Checks every section against the file length and the header's counts,
+    /// and every stored offset and link against the sections they index, so
+    /// lookups on the mapped file cannot go out of bounds.
+    fn validate(&self, map: &[u8]) -> Result<()> {
+        let count = self.count;
+        let expect = |name: &str, actual: usize, expected: usize| {
+            if actual == expected {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("{name} holds {actual} entries, expected {expected}"))
+            }
+        };
+        let dimension = match self.dimension {
+            Some(dimension) if dimension > 0 => dimension,
+            _ if count == 0 => 0,
+            _ => anyhow::bail!("{count} nodes but no dimension"),
+        };
+
+        expect("ids", cast::<u8>(map, self.ids)?.len(), count * 16)?;
+        expect("vectors", cast::<f32>(map, self.vectors)?.len(), count * dimension)?;
+        expect("deleted", cast::<u8>(map, self.deleted)?.len(), count)?;
+        let list_start = cast::<u32>(map, self.list_start)?;
+        expect("list_start", list_start.len(), count + 1)?;
+        let link_start = cast::<u32>(map, self.link_start)?;
+        let links = cast::<u32>(map, self.links)?;
+
+        // Every node has at least layer 0, and its lists are contiguous.
+        if list_start[0] != 0 || list_start.windows(2).any(|w| w[1] <= w[0]) {
+            anyhow::bail!("list_start is not strictly increasing from 0");
+        }
+        expect("link_start", link_start.len(), list_start[count] as usize + 1)?;
+        if link_start[0] != 0 || link_start.windows(2).any(|w| w[1] < w[0]) {
+            anyhow::bail!("link_start is not increasing from 0");
+        }
+        expect("links", links.len(), link_start[link_start.len() - 1] as usize)?;
+        // Searches follow a layer-`l` link into the target's layer-`l` list,
+        // so every target must exist and reach that layer.
+        let levels: Vec<usize> = list_start.windows(2).map(|w| (w[1] - w[0]) as usize).collect();
+        for (node, lists) in list_start.windows(2).enumerate() {
+            for (level, list) in (lists[0] as usize..lists[1] as usize).enumerate() {
+                let targets = &links[link_start[list] as usize..link_start[list + 1] as usize];
+                if targets.iter().any(|&target| levels.get(target as usize).is_none_or(|&l| l <= level)) {
+                    anyhow::bail!("node {node} links on layer {level} to a node missing from that layer");
+                }
+            }
+        }
+        if self.entry.is_some_and(|entry| entry as usize >= count) || (self.entry.is_none() && count > 0) {
+            anyhow::bail!("entry point {:?} is not a node", self.entry);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    marker: IndexMarker,
+    config: HnswConfig,
+    dimension: Option<usize>,
+    count: usize,
+    entry: Option<u32>,
+    ids: Section,
+    vectors: Section,
+    deleted: Section,
+    list_start: Section,
+    link_start: Section,
+    links: Section,
+}
+
+pub struct MmapIndex {
+    path: PathBuf,
+    map: Mmap,
+    header: Header,
+    /// Live mapped nodes.
+    positions: HashMap<Uuid, u32>,
+    /// Mapped nodes removed since the file was written.
+    removed: HashSet<u32>,
+    /// Prepared vectors added since the file was written.
+    delta: HashMap<Uuid, Vec<f32>>,
+}
+
+pub fn mmap_path(storage: &StorageManager, slot: &str) -> PathBuf {
+    let mut path = storage.path().as_os_str().to_owned();
+    if slot != DEFAULT_SLOT {
+        path.push(format!(".{slot}"));
+    }
+    path.push(".vmm");
+    PathBuf::from(path)
+}
+
+/// Opens the mapped index for `slot` if it matches the store and `config`,
+/// otherwise builds an `HnswIndex` with `config` from the `memories` table and
+/// writes it. A file that is unreadable, truncated or corrupt is rebuilt too.
+pub fn open_or_build(storage: &StorageManager, slot: &str, config: HnswConfig) -> Result<MmapIndex> {
+    let path = mmap_path(storage, slot);
+    let current = IndexMarker::current(storage, slot)?;
+    let dimension = storage.slot_dimension(slot)?;
+
+    if path.exists() {
+        match MmapIndex::open(&path) {
+            Ok(index)
+                if !index.header.config.same_graph(&config)
+                    || (index.header.count > 0 && dimension.is_some_and(|d| index.header.dimension != Some(d))) =>
+            {
+                tracing::warn!(
+                    stored_config = ?index.header.config,
+                    stored_dimension = ?index.header.dimension,
+                    requested_config = ?config,
+                    requested_dimension = ?dimension,
+                    slot,
+                    "memory-mapped index has a different configuration, rebuilding"
+                )
+            }
+            Ok(mut index) if index.header.marker == current => {
+                index.header.config.ef_search = config.ef_search;
+                return Ok(index);
+            }
+            Ok(index) => tracing::warn!(
+                stored = ?index.header.marker,
+                ?current,
+                slot,
+                "memory-mapped index diverged from storage, rebuilding"
+            ),
+            Err(err) => tracing::warn!(%err, slot, "unreadable memory-mapped index, rebuilding"),
+        }
+    }
+
+    let mut hnsw = HnswIndex::new(config);
+    super::persist::rebuild(storage, slot, &mut hnsw)?;
+    MmapIndex::write(&path, &hnsw, &current)?;
+    MmapIndex::open(&path)
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(ALIGN), 0);
+}
+
+fn section(buf: &mut Vec<u8>, bytes: &[u8]) -> Section {
+    pad(buf);
+    let offset = buf.len();
+    buf.extend_from_slice(bytes);
+    Section { offset, len: bytes.len() }
+}
+
+impl MmapIndex {
+    /// Writes `hnsw` to `path` stamped with `marker`, replacing any earlier
+    /// file atomically.
+    pub fn write(path: &Path, hnsw: &HnswIndex, marker: &IndexMarker) -> Result<()> {
+        let mut ids = Vec::new();
+        let mut vectors: Vec<f32> = Vec::new();
+        let mut deleted = Vec::new();
+        let mut list_start = vec![0u32];
+        let mut link_start = vec![0u32];
+        let mut links: Vec<u32> = Vec::new();
+        let mut dimension = None;
+        for (id, vector, layers, is_deleted) in hnsw.raw_nodes() {
+            ids.extend_from_slice(id.as_bytes());
+            vectors.extend_from_slice(vector);
+            dimension = Some(vector.len());
+            deleted.push(is_deleted as u8);
+            for layer in layers {
+                links.extend_from_slice(layer);
+                link_start.push(links.len() as u32);
+            }
+            list_start.push((link_start.len() - 1) as u32);
+        }
+
+        // Sections are laid out after the header, whose length depends on
+        // their offsets; place them relative to 0 first, then shift.
+        let mut body = Vec::new();
+        let mut header = Header {
+            marker: marker.clone(),
+            config: *hnsw.config(),
+            dimension,
+            count: deleted.len(),
+            entry: hnsw.entry_point(),
+            ids: section(&mut body, &ids),
+            vectors: section(&mut body, bytemuck::cast_slice(&vectors)),
+            deleted: section(&mut body, &deleted),
+            list_start: section(&mut body, bytemuck::cast_slice(&list_start)),
+            link_start: section(&mut body, bytemuck::cast_slice(&link_start)),
+            links: section(&mut body, bytemuck::cast_slice(&links)),
+        };
+        let mut json = serde_json::to_vec(&header)?;
+        // Offsets grow the JSON, so leave room for a few more digits each.
+        json.resize(json.len() + 64, b' ');
+        let base = (MAGIC.len() + 4 + json.len()).next_multiple_of(ALIGN);
+        for s in [&mut header.ids, &mut header.vectors, &mut header.deleted, &mut header.list_start, &mut header.link_start, &mut header.links] {
+            s.offset += base;
+        }
+        let mut header_json = serde_json::to_vec(&header)?;
+        if header_json.len() > json.len() {
+            anyhow::bail!("memory-mapped index header overflowed its reserved space");
+        }
+        header_json.resize(json.len(), b' ');
+
+        let tmp = path.with_extension("vmm.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(header_json.len() as u32).to_le_bytes())?;
+        writer.write_all(&header_json)?;
+        writer.write_all(&vec![0u8; base - (MAGIC.len() + 4 + header_json.len())])?;
+        writer.write_all(&body)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Maps `path` read-only.
+    pub fn open(path: &Path) -> Result<Self> {
+        if cfg!(target_endian = "big") {
+            anyhow::bail!("memory-mapped indexes are little-endian only");
+        }
+        let file = File::open(path)?;
+        // SAFETY: the file is only ever replaced by rename, never modified in
+        // place, so the mapped bytes stay valid for the life of the map.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < MAGIC.len() + 4 || &map[..MAGIC.len()] != MAGIC {
+            anyhow::bail!("{} is not a memory-mapped vector index", path.display());
+        }
+        let header_len = u32::from_le_bytes(map[MAGIC.len()..MAGIC.len() + 4].try_into()?) as usize;
+        let header_start = MAGIC.len() + 4;
+        let Some(header_bytes) = map.get(header_start..header_start + header_len) else {
+            anyhow::bail!("{} is truncated: header runs past the end of the file", path.display());
+        };
+        let header: Header = serde_json::from_slice(header_bytes)?;
+        header.validate(&map).map_err(|err| anyhow::anyhow!("{} is truncated or corrupt: {err}", path.display()))?;
+
+        let mut index = Self {
+            path: path.to_path_buf(),
+            map,
+            header,
+            positions: HashMap::new(),
+            removed: HashSet::new(),
+            delta: HashMap::new(),
+        };
+        index.positions = (0..index.header.count as u32)
+            .filter(|&node| index.map[index.header.deleted.offset + node as usize] == 0)
+            .map(|node| (index.id(node), node))
+            .collect();
+        Ok(index)
+    }
+
+    /// Rewrites the file with the delta and tombstones folded in, stamped
+    /// with the store's current marker, and remaps it.
+    ///
+    /// As with `persist::save`, call this only once every memory's `slot`
+    /// embedding is in the index. The graph is rebuilt from scratch without
+    /// graph bias.
+    pub fn compact(&mut self, storage: &StorageManager, slot: &str) -> Result<()> {
+        let mut hnsw = HnswIndex::new(self.header.config);
+        // Insertion order shapes the graph; keep it stable across compactions.
+        let mut live: Vec<(Uuid, u32)> = self.positions.iter().map(|(id, node)| (*id, *node)).collect();
+        live.sort_by_key(|(_, node)| *node);
+        for (id, node) in live {
+            hnsw.add(id, self.row(node))?;
+        }
+        let mut added: Vec<(&Uuid, &Vec<f32>)> = self.delta.iter().collect();
+        added.sort_by_key(|(id, _)| **id);
+        for (id, vector) in added {
+            hnsw.add(*id, vector)?;
+        }
+        Self::write(&self.path, &hnsw, &IndexMarker::current(storage, slot)?)?;
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    /// A section as `T`s; `Header::validate` checked every section on open.
+    fn slice<T: bytemuck::Pod>(&self, section: Section) -> &[T] {
+        cast(&self.map, section).expect("section validated on open")
+    }
+
+    fn id(&self, node: u32) -> Uuid {
+        let start = self.header.ids.offset + node as usize * 16;
+        Uuid::from_bytes(self.map[start..start + 16].try_into().expect("16-byte id"))
+    }
+
+    fn row(&self, node: u32) -> &[f32] {
+        let dim = self.header.dimension.unwrap_or(0);
+        &self.slice::<f32>(self.header.vectors)[node as usize * dim..(node as usize + 1) * dim]
+    }
+
+    fn metric_of(&self) -> Metric {
+        self.header.config.metric
+    }
+
+    fn is_live(&self, node: u32) -> bool {
+        self.map[self.header.deleted.offset + node as usize] == 0 && !self.removed.contains(&node)
+    }
+}
+
+impl Layers for MmapIndex {
+    fn similarity(&self, query: &[f32], node: u32) -> f32 {
+        self.metric_of().score_prepared(query, self.row(node))
+    }
+
+    fn links(&self, node: u32, level: usize) -> &[u32] {
+        let list = self.slice::<u32>(self.header.list_start)[node as usize] as usize + level;
+        let link_start = self.slice::<u32>(self.header.link_start);
+        &self.slice::<u32>(self.header.links)[link_start[list] as usize..link_start[list + 1] as usize]
+    }
+
+    fn top_level(&self, node: u32) -> usize {
+        let list_start = self.slice::<u32>(self.header.list_start);
+        (list_start[node as usize + 1] - list_start[node as usize]) as usize - 1
+    }
+}
+
+impl VectorIndex for MmapIndex {
+    fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.contains(id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, self.dimension())?;
+        self.delta.insert(id, self.metric_of().prepare(vector));
+        Ok(())
+    }
+
+    fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, self.dimension())?;
+        let query = self.metric_of().prepare(vector);
+        let mut top = TopK::new(k);
+
+        if let Some(entry) = descend(self, &query, self.header.entry, 0) {
+            let ef = self.header.config.ef_search.max(k);
+            let mut found = 0;
+            for c in search_layer(self, &query, &[entry], ef, 0) {
+                let id = self.id(c.node);
+                if self.is_live(c.node) && allowed(id) {
+                    top.push(id, c.score);
+                    found += 1;
+                }
+            }
+            // As in `HnswIndex`: a selective predicate or many tombstones can
+            // starve the beam, so fall back to an exact scan of the mapping.
+            if found < k {
+                top = TopK::new(k);
+                for node in 0..self.header.count as u32 {
+                    let id = self.id(node);
+                    if self.is_live(node) && allowed(id) {
+                        top.push(id, self.similarity(&query, node));
+                    }
+                }
+            }
+        }
+
+        top.extend(self.delta.iter()
+            .filter(|(id, _)| allowed(**id))
+            .map(|(id, row)| (*id, self.metric_of().score_prepared(&query, row))));
+        Ok(top.into_sorted_vec())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        if let Some(node) = self.positions.remove(&id) {
+            self.removed.insert(node);
+            return Ok(true);
+        }
+        Ok(self.delta.remove(&id).is_some())
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id) || self.delta.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len() + self.delta.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric_of()
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.header.dimension.or_else(|| self.delta.values().next().map(Vec::len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::testing::{memory, random_vectors};
+    use crate::storage::TempStorage;
+
+    fn store(n: usize) -> TempStorage {
+        let storage = TempStorage::new();
+        for vector in random_vectors(n, 8, 61) {
+            storage.save_memory(&memory(vector)).unwrap();
+        }
+        storage
+    }
+
+    #[test]
+    fn truncated_or_corrupt_files_are_errors_and_rebuilt() {
+        let storage = store(100);
+        let index = open_or_build(&storage, DEFAULT_SLOT, HnswConfig::default()).unwrap();
+        let path = mmap_path(&storage, DEFAULT_SLOT);
+        let bytes = fs::read(&path).unwrap();
+        let header = &index.header;
+        let sections = [header.ids, header.vectors, header.deleted, header.list_start, header.link_start, header.links];
+        let links = header.links;
+        drop(index);
+
+        // Cut inside the magic, the header and the end of every section.
+        let cuts = [0, 5, 20].into_iter().chain(sections.iter().map(|s| s.offset + s.len - 1));
+        for len in cuts {
+            fs::write(&path, &bytes[..len]).unwrap();
+            assert!(MmapIndex::open(&path).is_err(), "opened a file truncated to {len} bytes");
+        }
+
+        let mut corrupt = bytes.clone();
+        corrupt[links.offset..links.offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &corrupt).unwrap();
+        assert!(MmapIndex::open(&path).is_err());
+
+        let rebuilt = open_or_build(&storage, DEFAULT_SLOT, HnswConfig::default()).unwrap();
+        assert_eq!(rebuilt.len(), 100);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn config_mismatch_rebuilds() {
+        let storage = store(50);
+        open_or_build(&storage, DEFAULT_SLOT, HnswConfig::default()).unwrap();
+
+        let wider = HnswConfig { ef_search: 200, ..HnswConfig::default() };
+        let index = open_or_build(&storage, DEFAULT_SLOT, wider).unwrap();
+        assert_eq!(index.header.config.ef_search, 200);
+
+        let denser = HnswConfig { m: 8, ..HnswConfig::default() };
+        let index = open_or_build(&storage, DEFAULT_SLOT, denser).unwrap();
+        assert_eq!(index.header.config.m, 8);
+        drop(index);
+        assert_eq!(MmapIndex::open(&mmap_path(&storage, DEFAULT_SLOT)).unwrap().header.config.m, 8);
+    }
+}
@@ -1,14 +1,255 @@
+use crate::models::EmbeddingModel;
 use anyhow::Result;
+use std::collections::HashMap;
 use uuid::Uuid;
 
+pub mod adjacency;
+pub mod embed;
+pub mod flat;
+pub mod hnsw;
+pub mod matryoshka;
+pub mod metric;
+pub mod mmap;
+pub mod persist;
+pub mod quantized;
+pub mod text;
+pub(crate) mod topk;
 pub mod vector;
 
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use metric::Metric;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum IndexError {
+    #[error("memory {0} is already indexed; use upsert to replace its vector")]
+    DuplicateId(Uuid),
+    #[error("embedding has {actual} dimensions, expected {expected}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[error("embedding is empty")]
+    EmptyVector,
+    #[error("embedding value at position {position} is not finite")]
+    NonFinite { position: usize },
+    #[error("slot `{slot}` holds embeddings from {expected}, got {}", .actual.as_ref().map_or("an untracked model".to_string(), ToString::to_string))]
+    ModelMismatch { slot: String, expected: EmbeddingModel, actual: Option<EmbeddingModel> },
+    #[error("slot `{slot}` already holds embeddings from an untracked model; adopt or re-embed them before saving {model}")]
+    UntrackedVectors { slot: String, model: EmbeddingModel },
+}
+
+/// Checks that `vector` is non-empty, finite and, if `expected` is known, of that length.
+pub fn validate_vector(vector: &[f32], expected: Option<usize>) -> Result<(), IndexError> {
+    if vector.is_empty() {
+        return Err(IndexError::EmptyVector);
+    }
+    if let Some(expected) = expected
+        && vector.len() != expected
+    {
+        return Err(IndexError::DimensionMismatch { expected, actual: vector.len() });
+    }
+    if let Some(position) = vector.iter().position(|x| !x.is_finite()) {
+        return Err(IndexError::NonFinite { position });
+    }
+    Ok(())
+}
+
+/// Drops `id` from `ids` by moving the last id into its place, as
+/// `Vec::swap_remove` does, and keeps `positions` in step. Returns the
+/// position it held, to `swap_remove_row` from each per-vector buffer.
+pub(crate) fn swap_remove_id(ids: &mut Vec<Uuid>, positions: &mut HashMap<Uuid, usize>, id: Uuid) -> Option<usize> {
+    let pos = positions.remove(&id)?;
+    ids.swap_remove(pos);
+    if let Some(moved) = ids.get(pos) {
+        positions.insert(*moved, pos);
+    }
+    Some(pos)
+}
+
+/// `Vec::swap_remove` for a buffer of `width`-value rows.
+pub(crate) fn swap_remove_row<T: Copy>(rows: &mut Vec<T>, width: usize, pos: usize) {
+    if width == 0 || rows.is_empty() {
+        return;
+    }
+    let last = rows.len() / width - 1;
+    if pos != last {
+        rows.copy_within(last * width..(last + 1) * width, pos * width);
+    }
+    rows.truncate(last * width);
+}
+
+/// Nearest-neighbour index over memory embeddings.
+///
+/// The index is derived from the `memories` table: after `save_memory` changes
+/// an embedding call `upsert`, and after `delete_memory` call `remove`.
 pub trait VectorIndex {
+    /// Inserts a new vector. Fails with `IndexError::DuplicateId` if `id` is
+    /// present, or with a validation error if the vector does not fit the index.
     fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()>;
     fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>>;
+
+    /// Like `search`, but only ids accepted by `allowed` are scored, so up to
+    /// `k` allowed memories are returned however selective the predicate is.
+    ///
+    /// A candidate set is passed as `&|id| set.contains(&id)`.
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>>;
+
+    /// Answers several top-`k` queries at once, one result list per query.
+    ///
+    /// Indexes that scan their vectors override this to score every query in
+    /// a single pass over memory.
+    fn search_batch(&self, vectors: &[Vec<f32>], k: usize) -> Result<Vec<Vec<(Uuid, f32)>>> {
+        vectors.iter().map(|vector| self.search(vector, k)).collect()
+    }
+
+    /// Every indexed memory scoring at least `min_score`, best first.
+    fn search_range(&self, vector: &[f32], min_score: f32) -> Result<Vec<(Uuid, f32)>> {
+        self.search_range_filtered(vector, min_score, &|_| true)
+    }
+
+    /// `search_range` restricted to ids accepted by `allowed`.
+    ///
+    /// The default widens `search_filtered` until its worst hit falls below
+    /// the cutoff; exhaustive indexes override it with a single scan.
+    fn search_range_filtered(&self, vector: &[f32], min_score: f32, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        let mut k = 16;
+        loop {
+            let mut hits = self.search_filtered(vector, k, allowed)?;
+            let exhausted = hits.len() < k || k >= self.len();
+            if exhausted || hits.last().is_some_and(|(_, score)| *score < min_score) {
+                hits.retain(|(_, score)| *score >= min_score);
+                return Ok(hits);
+            }
+            k = k.saturating_mul(2);
+        }
+    }
+
+    /// Drops `id` from the index. Returns whether it was present.
+    fn remove(&mut self, id: Uuid) -> Result<bool>;
+    fn contains(&self, id: Uuid) -> bool;
+    fn len(&self) -> usize;
+    fn metric(&self) -> Metric;
+    /// Embedding length this index accepts, once known.
+    fn dimension(&self) -> Option<usize>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts `id`, replacing its previous vector if it was already indexed.
+    fn upsert(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        self.remove(id)?;
+        self.add(id, vector)
+    }
+}
+
+/// Full-precision embeddings by memory id and slot, used to re-rank
+/// approximate hits.
+pub trait VectorSource {
+    /// The `slot` embedding of `id`, or `None` if the memory or slot is missing.
+    fn get_vector(&self, id: Uuid, slot: &str) -> Result<Option<Vec<f32>>>;
 }
 
 pub trait GraphIndex {
     fn add_edge(&mut self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()>;
     fn get_neighbors(&self, node: Uuid) -> Result<Vec<(Uuid, f32)>>;
+    /// Sources of edges pointing at `node`, with their weights.
+    fn get_inbound_neighbors(&self, node: Uuid) -> Result<Vec<(Uuid, f32)>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::flat::FlatIndex;
+
+    #[test]
+    fn validate_vector_names_the_problem() {
+        assert_eq!(validate_vector(&[], None), Err(IndexError::EmptyVector));
+        assert_eq!(validate_vector(&[], Some(3)), Err(IndexError::EmptyVector));
+        assert_eq!(validate_vector(&[1.0, f32::NAN], None), Err(IndexError::NonFinite { position: 1 }));
+        assert_eq!(validate_vector(&[f32::INFINITY], None), Err(IndexError::NonFinite { position: 0 }));
+        assert_eq!(validate_vector(&[1.0, f32::NEG_INFINITY, 0.0], None), Err(IndexError::NonFinite { position: 1 }));
+        assert_eq!(validate_vector(&[1.0, 2.0], Some(3)), Err(IndexError::DimensionMismatch { expected: 3, actual: 2 }));
+        assert_eq!(validate_vector(&[1.0, 2.0, 3.0], Some(3)), Ok(()));
+        assert_eq!(validate_vector(&[-1.0], None), Ok(()));
+    }
+
+    #[test]
+    fn indexes_pin_their_dimension_on_first_add() {
+        let mut index = FlatIndex::new();
+        let rejected = |result: Result<()>| result.unwrap_err().downcast::<IndexError>().unwrap();
+        assert_eq!(rejected(index.add(Uuid::now_v7(), &[])), IndexError::EmptyVector);
+        assert_eq!(index.dimension(), None);
+
+        let id = Uuid::now_v7();
+        index.add(id, &[1.0, 0.0, 0.0]).unwrap();
+        assert_eq!(rejected(index.add(Uuid::now_v7(), &[1.0, 0.0])), IndexError::DimensionMismatch { expected: 3, actual: 2 });
+        assert_eq!(rejected(index.add(Uuid::now_v7(), &[0.0, f32::NAN, 0.0])), IndexError::NonFinite { position: 1 });
+        assert_eq!(rejected(index.add(id, &[0.0, 1.0, 0.0])), IndexError::DuplicateId(id));
+        assert_eq!(rejected(index.upsert(id, &[0.0, 1.0])), IndexError::DimensionMismatch { expected: 3, actual: 2 });
+        assert_eq!(
+            index.search(&[1.0, 0.0], 1).unwrap_err().downcast::<IndexError>().unwrap(),
+            IndexError::DimensionMismatch { expected: 3, actual: 2 }
+        );
+        assert_eq!(index.len(), 1);
+    }
+
+    /// Delegates the required methods to a `FlatIndex` and keeps every
+    /// default, so range search goes through the widening loop.
+    struct Widening(FlatIndex);
+
+    impl VectorIndex for Widening {
+        fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+            self.0.add(id, vector)
+        }
+
+        fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+            self.0.search(vector, k)
+        }
+
+        fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+            self.0.search_filtered(vector, k, allowed)
+        }
+
+        fn remove(&mut self, id: Uuid) -> Result<bool> {
+            self.0.remove(id)
+        }
+
+        fn contains(&self, id: Uuid) -> bool {
+            self.0.contains(id)
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn metric(&self) -> Metric {
+            self.0.metric()
+        }
+
+        fn dimension(&self) -> Option<usize> {
+            self.0.dimension()
+        }
+    }
+
+    #[test]
+    fn default_range_search_widens_past_the_first_k() {
+        // Memory `i` sits at distance `i` from the query, scoring 1 / (1 + i).
+        let mut index = Widening(FlatIndex::new().with_metric(Metric::Euclidean));
+        let ids: Vec<Uuid> = (0..100).map(|_| Uuid::now_v7()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            index.add(*id, &[i as f32, 0.0]).unwrap();
+        }
+        let within = |count: usize| 1.0 / (1.0 + count as f32 - 0.5);
+
+        for count in [0, 1, 15, 16, 17, 40, 100] {
+            let hits = index.search_range(&[0.0, 0.0], within(count)).unwrap();
+            assert_eq!(hits.iter().map(|(id, _)| *id).collect::<Vec<_>>(), ids[..count], "{count} in range");
+            assert_eq!(hits, index.0.search_range(&[0.0, 0.0], within(count)).unwrap());
+        }
+
+        let even = |id: Uuid| ids.iter().position(|x| *x == id).is_some_and(|i| i % 2 == 0);
+        let hits = index.search_range_filtered(&[0.0, 0.0], within(60), &even).unwrap();
+        assert_eq!(hits.len(), 30);
+        assert!(hits.iter().all(|(id, score)| even(*id) && *score >= within(60)));
+    }
 }
@@ -0,0 +1,170 @@
+//! Keeps a vector index in step with the `memories` table across restarts.
+//!
+//! A snapshot is written next to the redb file (`<db>.vidx` for the default
+//! slot, `<db>.<slot>.vidx` for named slots) together with the storage revision
+//! it reflects. On open, a snapshot whose revision, memory count or embedding
+//! model no longer matches the store is treated as diverged and the index is
+//! rebuilt from `list_memories`.
+
+use super::VectorIndex;
+use crate::models::{DEFAULT_SLOT, EmbeddingModel};
+use crate::storage::StorageManager;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Consistency marker stored alongside the serialized index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexMarker {
+    pub revision: u64,
+    pub memories: u64,
+    /// Model the slot was pinned to when the index was built.
+    #[serde(default)]
+    pub model: Option<EmbeddingModel>,
+}
+
+impl IndexMarker {
+    pub fn current(storage: &StorageManager, slot: &str) -> Result<Self> {
+        Ok(Self {
+            revision: storage.memories_revision()?,
+            memories: storage.memory_count()?,
+            model: storage.slot_model(slot)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<V> {
+    marker: IndexMarker,
+    index: V,
+}
+
+pub fn snapshot_path(storage: &StorageManager, slot: &str) -> PathBuf {
+    let mut path = storage.path().as_os_str().to_owned();
+    if slot != DEFAULT_SLOT {
+        path.push(format!(".{slot}"));
+    }
+    path.push(".vidx");
+    PathBuf::from(path)
+}
+
+/// Writes `index` to disk, stamped with the store's current revision.
+///
+/// Call this only once every memory's `slot` embedding in `storage` has been
+/// added to `index`; the stamp is what lets the next `load_or_rebuild` trust
+/// the snapshot.
+pub fn save<V: Serialize>(storage: &StorageManager, slot: &str, index: &V) -> Result<()> {
+    let path = snapshot_path(storage, slot);
+    let tmp = path.with_extension("vidx.tmp");
+    let snapshot = Snapshot {
+        marker: IndexMarker::current(storage, slot)?,
+        index,
+    };
+
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut writer, &snapshot)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Reads the marker of the on-disk snapshot, if there is one.
+pub fn stored_marker(storage: &StorageManager, slot: &str) -> Result<Option<IndexMarker>> {
+    #[derive(Deserialize)]
+    struct MarkerOnly {
+        marker: IndexMarker,
+    }
+
+    let path = snapshot_path(storage, slot);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let snapshot: MarkerOnly = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(Some(snapshot.marker))
+}
+
+/// True when the on-disk snapshot reflects the store's current contents.
+pub fn is_consistent(storage: &StorageManager, slot: &str) -> Result<bool> {
+    Ok(stored_marker(storage, slot)? == Some(IndexMarker::current(storage, slot)?))
+}
+
+/// Adds every stored memory's `slot` embedding to `index`, skipping memories
+/// without that slot.
+pub fn rebuild<V: VectorIndex>(storage: &StorageManager, slot: &str, index: &mut V) -> Result<()> {
+    for memory in storage.list_memories()? {
+        if let Some(vector) = memory.vector(slot) {
+            index.add(memory.id, vector)?;
+        }
+    }
+    Ok(())
+}
+
+/// An index that `load_or_rebuild` can restore from a snapshot.
+///
+/// A snapshot holds the vectors and the settings they were encoded with, but
+/// not runtime handles such as a rerank source, a graph or a thread count.
+/// Those come from the freshly configured index passed as `requested`.
+pub trait Restore: VectorIndex + Serialize + DeserializeOwned {
+    /// Whether vectors indexed under this snapshot's settings can serve an
+    /// index configured like `requested`.
+    fn same_config(&self, requested: &Self) -> bool;
+
+    /// Takes over `requested`'s runtime-only settings.
+    fn restore_runtime(&mut self, requested: Self);
+}
+
+/// Whether a stored dimension serves a requested one; an index with no fixed
+/// dimension accepts any.
+pub(super) fn dimension_matches(stored: Option<usize>, requested: Option<usize>) -> bool {
+    requested.is_none_or(|d| stored == Some(d))
+}
+
+/// Loads the persisted index if it matches the store, otherwise rebuilds it
+/// from the `memories` table and refreshes the snapshot.
+///
+/// The index from `empty` decides the configuration: a snapshot built with
+/// other settings is rebuilt, and a restored one takes its runtime settings.
+pub fn load_or_rebuild<V: Restore>(storage: &StorageManager, slot: &str, empty: impl FnOnce() -> V) -> Result<V> {
+    let path = snapshot_path(storage, slot);
+    let current = IndexMarker::current(storage, slot)?;
+    let mut index = empty();
+
+    if path.exists() {
+        match serde_json::from_reader::<_, Snapshot<V>>(BufReader::new(File::open(&path)?)) {
+            Ok(snapshot) if !snapshot.index.same_config(&index) => {
+                tracing::warn!(
+                    stored_metric = ?snapshot.index.metric(),
+                    stored_dimension = ?snapshot.index.dimension(),
+                    requested_metric = ?index.metric(),
+                    requested_dimension = ?index.dimension(),
+                    slot,
+                    "vector index snapshot has a different configuration, rebuilding"
+                )
+            }
+            // Every memory has a default-slot vector; named slots may be sparse.
+            Ok(snapshot)
+                if snapshot.marker == current
+                    && (slot != DEFAULT_SLOT || snapshot.index.len() as u64 == current.memories) =>
+            {
+                let mut restored = snapshot.index;
+                restored.restore_runtime(index);
+                return Ok(restored);
+            }
+            Ok(snapshot) => tracing::warn!(
+                stored = ?snapshot.marker,
+                ?current,
+                slot,
+                "vector index snapshot diverged from storage, rebuilding"
+            ),
+            Err(err) => tracing::warn!(%err, slot, "unreadable vector index snapshot, rebuilding"),
+        }
+    }
+
+    rebuild(storage, slot, &mut index)?;
+    save(storage, slot, &index)?;
+    Ok(index)
+}
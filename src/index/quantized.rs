@@ -0,0 +1,657 @@
+//! Compressed vector indexes for stores whose f32 embeddings do not fit in RAM.
+//!
+//! `ScalarQuantizedIndex` keeps one `i8` per dimension (4x smaller than f32);
+//! `ProductQuantizedIndex` keeps one byte per subspace (e.g. 96 bytes for a
+//! 1536-d vector split into 96 subspaces). Both return approximate scores. With
+//! `with_rerank`, the best `k * oversample` candidates are re-scored against
+//! the full-precision embeddings held in redb.
+
+use super::metric::{distance_to_similarity, dot, l1, squared_l2};
+use super::persist::{Restore, dimension_matches};
+use super::topk::TopK;
+use super::{IndexError, Metric, VectorIndex, VectorSource, swap_remove_id, swap_remove_row, validate_vector};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Exact re-ranking of approximate candidates against stored embeddings.
+#[derive(Clone)]
+pub struct Rerank {
+    source: Arc<dyn VectorSource + Send + Sync>,
+    /// Embedding slot the index was built from
+    slot: String,
+    oversample: usize,
+}
+
+impl Rerank {
+    /// Re-scores against `source`'s `slot` embeddings, the same slot the index
+    /// is built from. `oversample` is how many approximate candidates are
+    /// fetched per requested result.
+    pub fn new(source: Arc<dyn VectorSource + Send + Sync>, slot: impl Into<String>, oversample: usize) -> Self {
+        Self { source, slot: slot.into(), oversample: oversample.max(1) }
+    }
+
+    pub(super) fn candidates(&self, k: usize) -> usize {
+        k.saturating_mul(self.oversample)
+    }
+
+    pub(super) fn apply(&self, metric: Metric, query: &[f32], approx: Vec<(Uuid, f32)>, k: usize) -> Result<Vec<(Uuid, f32)>> {
+        let mut exact = TopK::new(k);
+        for (id, _) in approx {
+            if let Some(vector) = self.source.get_vector(id, &self.slot)? {
+                // A stored vector of another length comes from another model.
+                if vector.len() != query.len() {
+                    return Err(IndexError::DimensionMismatch { expected: query.len(), actual: vector.len() }.into());
+                }
+                exact.push(id, metric.score(query, &vector));
+            }
+        }
+        Ok(exact.into_sorted_vec())
+    }
+}
+
+// --- Scalar (int8) quantization ---
+
+/// Stores each vector as `i8` codes with a per-vector scale: `x ≈ code * scale`.
+///
+/// Under `Metric::Cosine` vectors are normalized before quantizing, so the
+/// full `i8` range is spent on direction rather than magnitude.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(from = "StoredScalar")]
+pub struct ScalarQuantizedIndex {
+    metric: Metric,
+    dimension: Option<usize>,
+    ids: Vec<Uuid>,
+    scales: Vec<f32>,
+    /// `ids.len() * dimension` codes, one row per vector.
+    codes: Vec<i8>,
+    #[serde(skip)]
+    positions: HashMap<Uuid, usize>,
+    #[serde(skip)]
+    rerank: Option<Rerank>,
+}
+
+#[derive(Deserialize)]
+struct StoredScalar {
+    metric: Metric,
+    dimension: Option<usize>,
+    ids: Vec<Uuid>,
+    scales: Vec<f32>,
+    codes: Vec<i8>,
+}
+
+impl From<StoredScalar> for ScalarQuantizedIndex {
+    fn from(stored: StoredScalar) -> Self {
+        let positions = stored.ids.iter().enumerate().map(|(pos, id)| (*id, pos)).collect();
+        Self {
+            metric: stored.metric,
+            dimension: stored.dimension,
+            ids: stored.ids,
+            scales: stored.scales,
+            codes: stored.codes,
+            positions,
+            rerank: None,
+        }
+    }
+}
+
+impl ScalarQuantizedIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    pub fn with_rerank(mut self, rerank: Rerank) -> Self {
+        self.rerank = Some(rerank);
+        self
+    }
+
+    fn encode(&self, vector: &[f32]) -> (f32, Vec<i8>) {
+        let prepared = self.metric.prepare(vector);
+        let max = prepared.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        let scale = if max == 0.0 { 1.0 } else { max / 127.0 };
+        let codes = prepared.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8).collect();
+        (scale, codes)
+    }
+
+    fn row(&self, pos: usize) -> &[i8] {
+        let dim = self.dimension.unwrap_or(0);
+        &self.codes[pos * dim..(pos + 1) * dim]
+    }
+
+    fn approx_score(&self, query: &[f32], pos: usize) -> f32 {
+        let scale = self.scales[pos];
+        let row = self.row(pos);
+        match self.metric {
+            Metric::Cosine | Metric::DotProduct => {
+                scale * query.iter().zip(row).map(|(q, c)| q * *c as f32).sum::<f32>()
+            }
+            Metric::Euclidean => {
+                let squared: f32 = query.iter().zip(row)
+                    .map(|(q, c)| {
+                        let d = q - *c as f32 * scale;
+                        d * d
+                    })
+                    .sum();
+                distance_to_similarity(squared.sqrt())
+            }
+            Metric::Manhattan => {
+                let distance: f32 = query.iter().zip(row).map(|(q, c)| (q - *c as f32 * scale).abs()).sum();
+                distance_to_similarity(distance)
+            }
+        }
+    }
+}
+
+impl VectorIndex for ScalarQuantizedIndex {
+    fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.positions.contains_key(&id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, self.dimension)?;
+        self.dimension = Some(vector.len());
+        let (scale, codes) = self.encode(vector);
+        self.positions.insert(id, self.ids.len());
+        self.ids.push(id);
+        self.scales.push(scale);
+        self.codes.extend(codes);
+        Ok(())
+    }
+
+    fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, self.dimension)?;
+        let query = self.metric.prepare(vector);
+        let fetch = self.rerank.as_ref().map_or(k, |r| r.candidates(k));
+
+        let mut top = TopK::new(fetch);
+        top.extend(self.ids.iter()
+            .enumerate()
+            .filter(|(_, id)| allowed(**id))
+            .map(|(pos, id)| (*id, self.approx_score(&query, pos))));
+        let scores = top.into_sorted_vec();
+
+        match &self.rerank {
+            Some(rerank) => rerank.apply(self.metric, vector, scores, k),
+            None => Ok(scores),
+        }
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        let Some(pos) = swap_remove_id(&mut self.ids, &mut self.positions, id) else {
+            return Ok(false);
+        };
+        swap_remove_row(&mut self.codes, self.dimension.unwrap_or(0), pos);
+        self.scales.swap_remove(pos);
+        Ok(true)
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+}
+
+impl Restore for ScalarQuantizedIndex {
+    fn same_config(&self, requested: &Self) -> bool {
+        self.metric == requested.metric && dimension_matches(self.dimension, requested.dimension)
+    }
+
+    fn restore_runtime(&mut self, requested: Self) {
+        self.rerank = requested.rerank;
+    }
+}
+
+// --- Product quantization ---
+
+#[derive(Debug, Clone, Copy)]
+pub struct PqConfig {
+    /// Number of subspaces; must divide the embedding dimension.
+    pub subspaces: usize,
+    /// Centroids per subspace, at most 256 so a code fits in one byte.
+    pub centroids: usize,
+    /// Lloyd iterations when training each codebook.
+    pub iterations: usize,
+    pub metric: Metric,
+}
+
+impl Default for PqConfig {
+    fn default() -> Self {
+        Self {
+            subspaces: 8,
+            centroids: 256,
+            iterations: 10,
+            metric: Metric::Cosine,
+        }
+    }
+}
+
+/// Splits each vector into `subspaces` chunks and stores the id of the nearest
+/// trained centroid for each chunk. Queries are scored with per-subspace lookup
+/// tables, which every supported metric decomposes into.
+#[derive(Serialize, Deserialize)]
+#[serde(from = "StoredProduct")]
+pub struct ProductQuantizedIndex {
+    metric: Metric,
+    dimension: usize,
+    subspaces: usize,
+    centroids: usize,
+    /// Per subspace, `centroids * sub_dim` values.
+    codebooks: Vec<Vec<f32>>,
+    ids: Vec<Uuid>,
+    /// `ids.len() * subspaces` centroid ids, one row per vector.
+    codes: Vec<u8>,
+    #[serde(skip)]
+    positions: HashMap<Uuid, usize>,
+    #[serde(skip)]
+    rerank: Option<Rerank>,
+}
+
+#[derive(Deserialize)]
+struct StoredProduct {
+    metric: Metric,
+    dimension: usize,
+    subspaces: usize,
+    centroids: usize,
+    codebooks: Vec<Vec<f32>>,
+    ids: Vec<Uuid>,
+    codes: Vec<u8>,
+}
+
+impl From<StoredProduct> for ProductQuantizedIndex {
+    fn from(stored: StoredProduct) -> Self {
+        let positions = stored.ids.iter().enumerate().map(|(pos, id)| (*id, pos)).collect();
+        Self {
+            metric: stored.metric,
+            dimension: stored.dimension,
+            subspaces: stored.subspaces,
+            centroids: stored.centroids,
+            codebooks: stored.codebooks,
+            ids: stored.ids,
+            codes: stored.codes,
+            positions,
+            rerank: None,
+        }
+    }
+}
+
+impl ProductQuantizedIndex {
+    /// Trains one codebook per subspace with k-means over `samples`.
+    ///
+    /// The samples fix the index dimension; a few thousand representative
+    /// embeddings are enough. No vectors are added by training.
+    pub fn train(samples: &[Vec<f32>], config: PqConfig) -> Result<Self> {
+        let Some(first) = samples.first() else {
+            anyhow::bail!("product quantization needs at least one training sample");
+        };
+        let dimension = first.len();
+        if config.subspaces == 0 || dimension % config.subspaces != 0 {
+            anyhow::bail!(
+                "{} subspaces do not evenly divide {dimension} dimensions",
+                config.subspaces
+            );
+        }
+        for sample in samples {
+            validate_vector(sample, Some(dimension))?;
+        }
+
+        let sub_dim = dimension / config.subspaces;
+        let centroids = config.centroids.clamp(1, 256).min(samples.len());
+        let prepared: Vec<Vec<f32>> = samples.iter().map(|s| config.metric.prepare(s)).collect();
+
+        let codebooks = (0..config.subspaces)
+            .map(|m| {
+                let chunks: Vec<&[f32]> = prepared.iter().map(|v| &v[m * sub_dim..(m + 1) * sub_dim]).collect();
+                kmeans(&chunks, centroids, config.iterations)
+            })
+            .collect();
+
+        Ok(Self {
+            metric: config.metric,
+            dimension,
+            subspaces: config.subspaces,
+            centroids,
+            codebooks,
+            ids: Vec::new(),
+            codes: Vec::new(),
+            positions: HashMap::new(),
+            rerank: None,
+        })
+    }
+
+    pub fn with_rerank(mut self, rerank: Rerank) -> Self {
+        self.rerank = Some(rerank);
+        self
+    }
+
+    fn sub_dim(&self) -> usize {
+        self.dimension / self.subspaces
+    }
+
+    fn centroid(&self, subspace: usize, code: usize) -> &[f32] {
+        let sub_dim = self.sub_dim();
+        &self.codebooks[subspace][code * sub_dim..(code + 1) * sub_dim]
+    }
+
+    fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        let prepared = self.metric.prepare(vector);
+        let sub_dim = self.sub_dim();
+        (0..self.subspaces)
+            .map(|m| {
+                let chunk = &prepared[m * sub_dim..(m + 1) * sub_dim];
+                nearest(chunk, &self.codebooks[m], sub_dim) as u8
+            })
+            .collect()
+    }
+
+    /// Per-subspace partial scores of `query` against every centroid.
+    fn lookup_tables(&self, query: &[f32]) -> Vec<f32> {
+        let sub_dim = self.sub_dim();
+        let mut tables = Vec::with_capacity(self.subspaces * self.centroids);
+        for m in 0..self.subspaces {
+            let chunk = &query[m * sub_dim..(m + 1) * sub_dim];
+            for c in 0..self.centroids {
+                let centroid = self.centroid(m, c);
+                tables.push(match self.metric {
+                    Metric::Cosine | Metric::DotProduct => dot(chunk, centroid),
+                    Metric::Euclidean => squared_l2(chunk, centroid),
+                    Metric::Manhattan => l1(chunk, centroid),
+                });
+            }
+        }
+        tables
+    }
+
+    fn approx_score(&self, tables: &[f32], pos: usize) -> f32 {
+        let row = &self.codes[pos * self.subspaces..(pos + 1) * self.subspaces];
+        let total: f32 = row.iter()
+            .enumerate()
+            .map(|(m, code)| tables[m * self.centroids + *code as usize])
+            .sum();
+        match self.metric {
+            Metric::Cosine | Metric::DotProduct => total,
+            Metric::Euclidean => distance_to_similarity(total.sqrt()),
+            Metric::Manhattan => distance_to_similarity(total),
+        }
+    }
+}
+
+impl VectorIndex for ProductQuantizedIndex {
+    fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.positions.contains_key(&id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, Some(self.dimension))?;
+        let codes = self.encode(vector);
+        self.positions.insert(id, self.ids.len());
+        self.ids.push(id);
+        self.codes.extend(codes);
+        Ok(())
+    }
+
+    fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(vector, Some(self.dimension))?;
+        let tables = self.lookup_tables(&self.metric.prepare(vector));
+        let fetch = self.rerank.as_ref().map_or(k, |r| r.candidates(k));
+
+        let mut top = TopK::new(fetch);
+        top.extend(self.ids.iter()
+            .enumerate()
+            .filter(|(_, id)| allowed(**id))
+            .map(|(pos, id)| (*id, self.approx_score(&tables, pos))));
+        let scores = top.into_sorted_vec();
+
+        match &self.rerank {
+            Some(rerank) => rerank.apply(self.metric, vector, scores, k),
+            None => Ok(scores),
+        }
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        let Some(pos) = swap_remove_id(&mut self.ids, &mut self.positions, id) else {
+            return Ok(false);
+        };
+        swap_remove_row(&mut self.codes, self.subspaces, pos);
+        Ok(true)
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.dimension)
+    }
+}
+
+impl Restore for ProductQuantizedIndex {
+    /// Codebooks are not compared: the snapshot's codes are only meaningful
+    /// with the codebooks they were encoded against, which it keeps.
+    fn same_config(&self, requested: &Self) -> bool {
+        self.metric == requested.metric
+            && self.dimension == requested.dimension
+            && self.subspaces == requested.subspaces
+            && self.centroids == requested.centroids
+    }
+
+    fn restore_runtime(&mut self, requested: Self) {
+        self.rerank = requested.rerank;
+    }
+}
+
+// --- Helpers ---
+
+/// Index of the centroid in the flat `codebook` closest to `point`.
+fn nearest(point: &[f32], codebook: &[f32], sub_dim: usize) -> usize {
+    codebook.chunks_exact(sub_dim)
+        .enumerate()
+        .map(|(c, centroid)| (c, squared_l2(point, centroid)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map_or(0, |(c, _)| c)
+}
+
+/// Lloyd's k-means, seeded with evenly strided points so training is deterministic.
+fn kmeans(points: &[&[f32]], k: usize, iterations: usize) -> Vec<f32> {
+    let sub_dim = points[0].len();
+    let stride = points.len() as f32 / k as f32;
+    let mut codebook: Vec<f32> = (0..k)
+        .flat_map(|c| points[(c as f32 * stride) as usize].iter().copied())
+        .collect();
+
+    for _ in 0..iterations {
+        let mut sums = vec![0.0f32; k * sub_dim];
+        let mut counts = vec![0usize; k];
+        for point in points {
+            let c = nearest(point, &codebook, sub_dim);
+            counts[c] += 1;
+            for (sum, x) in sums[c * sub_dim..(c + 1) * sub_dim].iter_mut().zip(point.iter()) {
+                *sum += x;
+            }
+        }
+        // Empty clusters keep their previous centroid.
+        for c in 0..k {
+            if counts[c] == 0 {
+                continue;
+            }
+            for d in 0..sub_dim {
+                codebook[c * sub_dim + d] = sums[c * sub_dim + d] / counts[c] as f32;
+            }
+        }
+    }
+    codebook
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::persist;
+    use crate::index::testing::{check_remove_and_upsert, ground_truth, memory, random_vectors, recall_at_k};
+    use crate::models::DEFAULT_SLOT;
+    use crate::storage::TempStorage;
+
+    struct Vectors(HashMap<Uuid, Vec<f32>>);
+
+    impl VectorSource for Vectors {
+        fn get_vector(&self, id: Uuid, _slot: &str) -> Result<Option<Vec<f32>>> {
+            Ok(self.0.get(&id).cloned())
+        }
+    }
+
+    #[test]
+    fn rerank_survives_snapshot_round_trip() {
+        let storage = TempStorage::new();
+        let mut vectors = HashMap::new();
+        for vector in random_vectors(200, 16, 7) {
+            let memory = memory(vector);
+            storage.save_memory(&memory).unwrap();
+            vectors.insert(memory.id, memory.embedding);
+        }
+        let source: Arc<dyn VectorSource + Send + Sync> = Arc::new(Vectors(vectors.clone()));
+        let empty = || ScalarQuantizedIndex::new().with_rerank(Rerank::new(source.clone(), DEFAULT_SLOT, 4));
+
+        let built = persist::load_or_rebuild(&storage, DEFAULT_SLOT, empty).unwrap();
+        let loaded = persist::load_or_rebuild(&storage, DEFAULT_SLOT, empty).unwrap();
+        assert!(persist::is_consistent(&storage, DEFAULT_SLOT).unwrap());
+
+        for query in random_vectors(10, 16, 99) {
+            let before = built.search(&query, 5).unwrap();
+            let after = loaded.search(&query, 5).unwrap();
+            assert_eq!(before, after);
+            for (id, score) in after {
+                assert_eq!(score, Metric::Cosine.score(&query, &vectors[&id]), "score is not re-ranked");
+            }
+        }
+    }
+
+    #[test]
+    fn product_quantization_recall_against_exact_search() {
+        let vectors = random_vectors(1000, 32, 11);
+        let ids: Vec<Uuid> = (0..vectors.len()).map(|_| Uuid::now_v7()).collect();
+        let exact = ground_truth(Metric::Cosine, &ids, &vectors);
+        let queries = random_vectors(50, 32, 12);
+
+        let config = PqConfig { subspaces: 8, centroids: 64, ..PqConfig::default() };
+        let mut approx = ProductQuantizedIndex::train(&vectors, config).unwrap();
+        let source: Arc<dyn VectorSource + Send + Sync> =
+            Arc::new(Vectors(ids.iter().copied().zip(vectors.iter().cloned()).collect()));
+        let mut reranked = ProductQuantizedIndex::train(&vectors, config).unwrap().with_rerank(Rerank::new(source, DEFAULT_SLOT, 10));
+        for (id, vector) in ids.iter().zip(&vectors) {
+            approx.add(*id, vector).unwrap();
+            reranked.add(*id, vector).unwrap();
+        }
+
+        let raw = recall_at_k(&approx, &exact, &queries, 10);
+        let rescored = recall_at_k(&reranked, &exact, &queries, 10);
+        assert!(raw >= 0.3, "raw PQ recall@10 {raw}");
+        assert!(rescored >= 0.9 && rescored >= raw, "reranked PQ recall@10 {rescored}");
+    }
+
+    #[test]
+    fn product_quantization_training_and_encoding() {
+        let samples = random_vectors(64, 12, 5);
+        let uneven = PqConfig { subspaces: 5, ..PqConfig::default() };
+        assert!(ProductQuantizedIndex::train(&samples, uneven).is_err());
+        assert!(ProductQuantizedIndex::train(&[], PqConfig::default()).is_err());
+
+        // With a centroid per sample, every training vector encodes exactly.
+        let config = PqConfig { subspaces: 4, centroids: 256, ..PqConfig::default() };
+        let mut index = ProductQuantizedIndex::train(&samples, config).unwrap();
+        assert_eq!(index.centroids, samples.len());
+        let ids: Vec<Uuid> = samples.iter().map(|_| Uuid::now_v7()).collect();
+        for (id, sample) in ids.iter().zip(&samples) {
+            index.add(*id, sample).unwrap();
+        }
+        assert!(index.add(ids[0], &samples[0]).is_err());
+        for (id, sample) in ids.iter().zip(&samples) {
+            let (best, score) = index.search(sample, 1).unwrap()[0];
+            assert_eq!(best, *id);
+            assert!((score - 1.0).abs() < 1e-4, "self-similarity {score}");
+        }
+    }
+
+    #[test]
+    fn remove_and_upsert_keep_positions() {
+        let vectors = random_vectors(7, 16, 3);
+        check_remove_and_upsert(&mut ScalarQuantizedIndex::new(), &vectors);
+        // A centroid per vector, so codes are exact and only positions are under test.
+        let config = PqConfig { subspaces: 4, ..PqConfig::default() };
+        check_remove_and_upsert(&mut ProductQuantizedIndex::train(&vectors, config).unwrap(), &vectors);
+    }
+
+    #[test]
+    fn rerank_reads_the_index_slot() {
+        // Both slots have the same dimension, so scoring against the wrong
+        // one would go unnoticed without comparing the scores.
+        let storage = TempStorage::new();
+        let mut summaries = HashMap::new();
+        for (content, summary) in random_vectors(50, 8, 31).into_iter().zip(random_vectors(50, 8, 32)) {
+            let mut memory = memory(content);
+            memory.embeddings.insert("summary".to_string(), summary.clone());
+            storage.save_memory(&memory).unwrap();
+            summaries.insert(memory.id, summary);
+        }
+        let source: Arc<dyn VectorSource + Send + Sync> = storage.shared();
+        let empty = || ScalarQuantizedIndex::new().with_rerank(Rerank::new(source.clone(), "summary", 4));
+
+        let built = persist::load_or_rebuild(&storage, "summary", empty).unwrap();
+        assert_eq!(built.len(), 50);
+        assert!(persist::is_consistent(&storage, "summary").unwrap());
+        assert!(!persist::is_consistent(&storage, DEFAULT_SLOT).unwrap());
+        let loaded = persist::load_or_rebuild(&storage, "summary", empty).unwrap();
+
+        for query in random_vectors(5, 8, 33) {
+            let results = loaded.search(&query, 5).unwrap();
+            assert_eq!(results, built.search(&query, 5).unwrap());
+            for (id, score) in results {
+                assert_eq!(score, Metric::Cosine.score(&query, &summaries[&id]));
+            }
+        }
+    }
+
+    #[test]
+    fn rerank_rejects_vectors_of_another_dimension() {
+        let id = Uuid::now_v7();
+        let source: Arc<dyn VectorSource + Send + Sync> = Arc::new(Vectors(HashMap::from([(id, vec![1.0; 8])])));
+        let mut index = ScalarQuantizedIndex::new().with_rerank(Rerank::new(source, "summary", 2));
+        index.add(id, &[1.0, 0.0, 0.0, 0.0]).unwrap();
+        let err = index.search(&[1.0, 0.0, 0.0, 0.0], 1).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&IndexError::DimensionMismatch { expected: 4, actual: 8 }));
+    }
+}
@@ -0,0 +1,88 @@
+//! Seeded data and recall measurement shared by the index tests.
+
+use super::flat::FlatIndex;
+use super::{Metric, VectorIndex};
+use crate::models::{Memory, MemoryType};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// `n` vectors of `dimension` values in `-1..1` from a xorshift generator,
+/// so every run sees the same data.
+pub(crate) fn random_vectors(n: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut state = seed.max(1);
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 40) as f32 / (1u64 << 23) as f32 * 2.0 - 1.0
+    };
+    (0..n).map(|_| (0..dimension).map(|_| next()).collect()).collect()
+}
+
+/// A semantic memory holding `embedding`.
+pub(crate) fn memory(embedding: Vec<f32>) -> Memory {
+    Memory::new(
+        "test memory".to_string(),
+        embedding,
+        MemoryType::Semantic { confidence: 1.0, source: "test".to_string() },
+    )
+}
+
+/// Exact index over `vectors`, keyed by `ids`.
+pub(crate) fn ground_truth(metric: Metric, ids: &[Uuid], vectors: &[Vec<f32>]) -> FlatIndex {
+    let mut flat = FlatIndex::new().with_metric(metric);
+    for (id, vector) in ids.iter().zip(vectors) {
+        flat.add(*id, vector).unwrap();
+    }
+    flat
+}
+
+/// Mean share of the exact top `k` that `index` also returns, over `queries`.
+pub(crate) fn recall_at_k<V: VectorIndex + ?Sized>(index: &V, exact: &FlatIndex, queries: &[Vec<f32>], k: usize) -> f32 {
+    let mut found = 0;
+    for query in queries {
+        let expected: HashSet<Uuid> = exact.search(query, k).unwrap().into_iter().map(|(id, _)| id).collect();
+        found += index.search(query, k).unwrap().iter().filter(|(id, _)| expected.contains(id)).count();
+    }
+    found as f32 / (queries.len() * k) as f32
+}
+
+/// Adds the first five of `vectors`, removes the last and then a middle id,
+/// which moves the last id into the gap, then upserts and removes the moved
+/// id and upserts a new one. After each step every remaining id must be
+/// found by its own vector. `vectors` needs seven entries that are each
+/// their own nearest neighbour under `index`'s metric.
+pub(crate) fn check_remove_and_upsert<V: VectorIndex + ?Sized>(index: &mut V, vectors: &[Vec<f32>]) {
+    let ids: Vec<Uuid> = (0..7).map(|_| Uuid::now_v7()).collect();
+    for (id, vector) in ids.iter().zip(&vectors[..5]) {
+        index.add(*id, vector).unwrap();
+    }
+    let assert_indexed = |index: &V, expected: &[(Uuid, &Vec<f32>)]| {
+        assert_eq!(index.len(), expected.len());
+        for (id, vector) in expected {
+            assert!(index.contains(*id));
+            assert_eq!(index.search(vector, 1).unwrap()[0].0, *id, "position of {id} is stale");
+        }
+        let all: HashSet<Uuid> = index.search(expected[0].1, 10).unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(all, expected.iter().map(|(id, _)| *id).collect());
+    };
+
+    assert!(index.remove(ids[4]).unwrap());
+    assert!(!index.contains(ids[4]));
+    assert_indexed(index, &[(ids[0], &vectors[0]), (ids[1], &vectors[1]), (ids[2], &vectors[2]), (ids[3], &vectors[3])]);
+
+    // `ids[3]` is last, so it moves into the middle slot.
+    assert!(index.remove(ids[1]).unwrap());
+    assert!(!index.remove(ids[1]).unwrap());
+    assert!(!index.contains(ids[1]));
+    assert_indexed(index, &[(ids[0], &vectors[0]), (ids[2], &vectors[2]), (ids[3], &vectors[3])]);
+
+    index.upsert(ids[3], &vectors[5]).unwrap();
+    assert_indexed(index, &[(ids[0], &vectors[0]), (ids[2], &vectors[2]), (ids[3], &vectors[5])]);
+
+    index.upsert(ids[6], &vectors[6]).unwrap();
+    assert_indexed(index, &[(ids[0], &vectors[0]), (ids[2], &vectors[2]), (ids[3], &vectors[5]), (ids[6], &vectors[6])]);
+
+    assert!(index.remove(ids[3]).unwrap());
+    assert_indexed(index, &[(ids[0], &vectors[0]), (ids[2], &vectors[2]), (ids[6], &vectors[6])]);
+}
@@ -0,0 +1,66 @@
+//! Lexical scoring over `Memory.content`.
+//!
+//! The inverted index itself lives in redb (`text_postings`, `text_docs`) and
+//! is maintained by `StorageManager` inside the same write transaction as the
+//! memory, so it can never drift from the `memories` table. This module holds
+//! the tokenizer and the BM25 scoring both sides agree on.
+
+use std::collections::HashMap;
+
+/// English function words that match nearly every memory; indexing them
+/// would only grow the largest posting lists.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it", "no", "not",
+    "of", "on", "or", "such", "that", "the", "their", "then", "there", "these", "they", "this", "to", "was",
+    "will", "with",
+];
+
+/// Lowercased alphanumeric runs minus `STOPWORDS`, e.g. "Commander Lewis's
+/// flush of the tank" -> `["commander", "lewis", "s", "flush", "tank"]`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .filter(|token| !STOPWORDS.contains(&token.as_str()))
+        .collect()
+}
+
+/// Term counts and token length of one document.
+pub fn term_frequencies(text: &str) -> (HashMap<String, u32>, u32) {
+    let tokens = tokenize(text);
+    let len = tokens.len() as u32;
+    let mut counts = HashMap::new();
+    for token in tokens {
+        *counts.entry(token).or_insert(0) += 1;
+    }
+    (counts, len)
+}
+
+/// Okapi BM25 parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct Bm25 {
+    /// Term-frequency saturation.
+    pub k1: f32,
+    /// Document-length normalisation, 0 (none) to 1 (full).
+    pub b: f32,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    pub fn idf(&self, docs: u64, doc_freq: u64) -> f32 {
+        let (n, df) = (docs as f32, doc_freq as f32);
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    /// Contribution of one query term to one document's score.
+    pub fn term_score(&self, idf: f32, term_freq: u32, doc_len: u32, avg_doc_len: f32) -> f32 {
+        let tf = term_freq as f32;
+        let norm = if avg_doc_len > 0.0 { doc_len as f32 / avg_doc_len } else { 1.0 };
+        idf * tf * (self.k1 + 1.0) / (tf + self.k1 * (1.0 - self.b + self.b * norm))
+    }
+}
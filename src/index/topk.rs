@@ -0,0 +1,114 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use uuid::Uuid;
+
+/// Bounded min-heap keeping the `k` highest-scoring ids seen so far.
+///
+/// Equal scores are ranked by id, larger first, so the result does not
+/// depend on the order ids are pushed or heaps merged.
+pub(crate) struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<Scored>>,
+}
+
+struct Scored(f32, Uuid);
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+impl TopK {
+    pub(crate) fn new(k: usize) -> Self {
+        Self { k, heap: BinaryHeap::with_capacity(k.saturating_add(1).min(4096)) }
+    }
+
+    pub(crate) fn push(&mut self, id: Uuid, score: f32) {
+        if self.k == 0 {
+            return;
+        }
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(Scored(score, id)));
+        } else if let Some(mut min) = self.heap.peek_mut()
+            && Scored(score, id) > min.0
+        {
+            *min = Reverse(Scored(score, id));
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: TopK) {
+        for Reverse(Scored(score, id)) in other.heap {
+            self.push(id, score);
+        }
+    }
+
+    /// Drains the heap into `(id, score)` pairs, best first.
+    pub(crate) fn into_sorted_vec(self) -> Vec<(Uuid, f32)> {
+        self.heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(Scored(score, id))| (id, score))
+            .collect()
+    }
+}
+
+impl Extend<(Uuid, f32)> for TopK {
+    fn extend<I: IntoIterator<Item = (Uuid, f32)>>(&mut self, iter: I) {
+        for (id, score) in iter {
+            self.push(id, score);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Best first, equal scores larger id first.
+    fn sorted(mut scored: Vec<(Uuid, f32)>, k: usize) -> Vec<(Uuid, f32)> {
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+        scored.truncate(k);
+        scored
+    }
+
+    #[test]
+    fn keeps_the_best_k_with_ties_broken_by_id() {
+        // Scores from a handful of values, so most of them tie.
+        let scored: Vec<(Uuid, f32)> = (0..200).map(|i| (Uuid::now_v7(), ((i * 7) % 5) as f32 - 2.0)).collect();
+        for k in [0, 1, 7, 40, 200, 250] {
+            let mut top = TopK::new(k);
+            top.extend(scored.iter().rev().copied());
+            assert_eq!(top.into_sorted_vec(), sorted(scored.clone(), k), "k = {k}");
+
+            let (left, right) = scored.split_at(83);
+            let mut merged = TopK::new(k);
+            merged.extend(left.iter().copied());
+            let mut other = TopK::new(k);
+            other.extend(right.iter().copied());
+            merged.merge(other);
+            assert_eq!(merged.into_sorted_vec(), sorted(scored.clone(), k), "merged, k = {k}");
+        }
+    }
+
+    #[test]
+    fn orders_negative_and_infinite_scores() {
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::now_v7()).collect();
+        let mut top = TopK::new(3);
+        top.extend([(ids[0], -1.0), (ids[1], f32::NEG_INFINITY), (ids[2], 0.5), (ids[3], f32::INFINITY)]);
+        assert_eq!(top.into_sorted_vec(), [(ids[3], f32::INFINITY), (ids[2], 0.5), (ids[0], -1.0)]);
+    }
+}
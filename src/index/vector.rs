@@ -1,27 +1,84 @@
-use super::VectorIndex;
+use super::persist::{Restore, dimension_matches};
+use super::{IndexError, Metric, VectorIndex, validate_vector};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
+#[derive(Default, Serialize, Deserialize)]
+#[serde(from = "StoredVectors")]
 pub struct SimpleVectorIndex {
+    metric: Metric,
+    /// Fixed at creation via `with_dimension`, or by the first `add`.
+    dimension: Option<usize>,
     vectors: Vec<(Uuid, Vec<f32>)>,
+    /// Position of each id in `vectors`; rebuilt on deserialization.
+    #[serde(skip)]
+    positions: HashMap<Uuid, usize>,
+}
+
+#[derive(Deserialize)]
+struct StoredVectors {
+    #[serde(default)]
+    metric: Metric,
+    #[serde(default)]
+    dimension: Option<usize>,
+    vectors: Vec<(Uuid, Vec<f32>)>,
+}
+
+impl From<StoredVectors> for SimpleVectorIndex {
+    fn from(stored: StoredVectors) -> Self {
+        let positions = stored.vectors.iter()
+            .enumerate()
+            .map(|(pos, (id, _))| (*id, pos))
+            .collect();
+        Self {
+            metric: stored.metric,
+            dimension: stored.dimension,
+            vectors: stored.vectors,
+            positions,
+        }
+    }
 }
 
 impl SimpleVectorIndex {
     pub fn new() -> Self {
-        Self { vectors: Vec::new() }
+        Self::default()
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
     }
 }
 
 impl VectorIndex for SimpleVectorIndex {
     fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if self.positions.contains_key(&id) {
+            return Err(IndexError::DuplicateId(id).into());
+        }
+        validate_vector(vector, self.dimension)?;
+        self.dimension = Some(vector.len());
+        self.positions.insert(id, self.vectors.len());
         self.vectors.push((id, vector.to_vec()));
         Ok(())
     }
 
     fn search(&self, query_vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+        self.search_filtered(query_vector, k, &|_| true)
+    }
+
+    fn search_filtered(&self, query_vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        validate_vector(query_vector, self.dimension)?;
         let mut scores: Vec<(Uuid, f32)> = self.vectors.iter()
+            .filter(|(id, _)| allowed(*id))
             .map(|(id, vec)| {
-                let score = cosine_similarity(query_vector, vec);
+                let score = self.metric.score(query_vector, vec);
                 (*id, score)
             })
             .collect();
@@ -30,15 +87,67 @@ impl VectorIndex for SimpleVectorIndex {
         scores.truncate(k);
         Ok(scores)
     }
+
+    fn search_range_filtered(&self, query_vector: &[f32], min_score: f32, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        let mut hits = self.search_filtered(query_vector, self.vectors.len(), allowed)?;
+        hits.retain(|(_, score)| *score >= min_score);
+        Ok(hits)
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<bool> {
+        let Some(pos) = self.positions.remove(&id) else {
+            return Ok(false);
+        };
+        self.vectors.swap_remove(pos);
+        if let Some((moved, _)) = self.vectors.get(pos) {
+            self.positions.insert(*moved, pos);
+        }
+        Ok(true)
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn upsert(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        match self.positions.get(&id) {
+            Some(&pos) => {
+                validate_vector(vector, self.dimension)?;
+                self.vectors[pos].1 = vector.to_vec();
+            }
+            None => self.add(id, vector)?,
+        }
+        Ok(())
+    }
+}
+
+impl Restore for SimpleVectorIndex {
+    fn same_config(&self, requested: &Self) -> bool {
+        self.metric == requested.metric && dimension_matches(self.dimension, requested.dimension)
+    }
+
+    fn restore_runtime(&mut self, _requested: Self) {}
 }
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if norm_a == 0.0 || norm_b == 0.0 {
-        0.0
-    } else {
-        dot_product / (norm_a * norm_b)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::testing::{check_remove_and_upsert, random_vectors};
+
+    #[test]
+    fn remove_and_upsert_keep_positions() {
+        check_remove_and_upsert(&mut SimpleVectorIndex::new(), &random_vectors(7, 16, 3));
     }
 }
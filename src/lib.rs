@@ -1,4 +1,5 @@
 pub mod models;
 pub mod storage;
 pub mod index;
+pub mod graph;
 pub mod query;
@@ -1,7 +1,7 @@
-use memory_graph::models::{Memory, MemoryType};
+use memory_graph::models::{DEFAULT_SLOT, Memory, MemoryType};
 use memory_graph::storage::StorageManager;
-use memory_graph::index::{VectorIndex, vector::SimpleVectorIndex};
-use memory_graph::query::{Query, Search, VectorSearch, Traverse, engine::QueryEngine};
+use memory_graph::index::{persist, vector::SimpleVectorIndex};
+use memory_graph::query::{Query, Search, VectorSearch, Traverse, Direction, engine::QueryEngine};
 use anyhow::Result;
 
 fn main() -> Result<()> {
@@ -9,9 +9,6 @@ fn main() -> Result<()> {
 
     // Initialize storage
     let storage = StorageManager::new("memory_graph.db")?;
-    
-    // Initialize vector index (in-memory for now)
-    let mut vector_index = SimpleVectorIndex::new();
 
     // Create two sample memories
     let memory1 = Memory::new(
@@ -47,10 +44,9 @@ fn main() -> Result<()> {
     storage.save_memory(&memory2)?;
     storage.save_memory(&memory3)?;
     
-    // Add to vector index
-    vector_index.add(memory1.id, &memory1.embedding)?;
-    vector_index.add(memory2.id, &memory2.embedding)?;
-    vector_index.add(memory3.id, &memory3.embedding)?;
+    // Open the vector index: reuses the on-disk snapshot if it is still in
+    // step with storage, otherwise rebuilds it from the memories table.
+    let vector_index = persist::load_or_rebuild(&storage, DEFAULT_SLOT, SimpleVectorIndex::new)?;
 
     // Create an edge between them
     println!("Creating edge: {} -> {}", memory1.id, memory2.id);
@@ -64,16 +60,25 @@ fn main() -> Result<()> {
     let query = Query {
         filter: None,
         search: Some(Search {
-            vector: VectorSearch {
+            vector: Some(VectorSearch {
                 text: None,
                 embedding: Some(vec![0.1, 0.2, 0.3]), // Exact match for memory1
                 threshold: None,
-            }
+                radius: false,
+                slot: None,
+            }),
+            text: None,
+            fusion: None,
         }),
         traverse: Some(Traverse {
-            direction: "outbound".to_string(),
+            direction: Direction::Outbound,
             edge_types: None,
+            exclude_edge_types: None,
+            edge_weights: None,
             depth: Some(1),
+            attenuation: None,
+            max_nodes: None,
+            mode: None,
         }),
         rank_by: None,
         limit: Some(5),
@@ -83,8 +88,9 @@ fn main() -> Result<()> {
     let results = engine.execute(query)?;
 
     println!("Found {} results:", results.len());
-    for mem in results {
-        println!("- [{:?}] {} (ID: {})", mem.memory_type, mem.content, mem.id);
+    for result in results {
+        let mem = result.memory;
+        println!("- [{:?}] {} (ID: {}, score {:.3})", mem.memory_type, mem.content, mem.id, result.score);
     }
 
     Ok(())
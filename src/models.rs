@@ -12,8 +12,21 @@ pub struct Memory {
     /// The raw text content or payload
     pub content: String,
     
-    /// 384D - 1536D vector embedding
+    /// 384D - 1536D vector embedding (the `content` slot)
     pub embedding: Vec<f32>,
+
+    /// Additional named embeddings ("summary", "emotion", ...), each indexed
+    /// separately and free to use its own dimension
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub embeddings: HashMap<String, Vec<f32>>,
+
+    /// Model that produced `embedding`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<EmbeddingModel>,
+
+    /// Models that produced the named `embeddings`, by slot
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub embedding_models: HashMap<String, EmbeddingModel>,
     
     /// Cognitive classification
     pub memory_type: MemoryType,
@@ -34,6 +47,29 @@ pub struct Memory {
     pub decay_rate: f32,        // How fast this memory fades
 }
 
+/// Slot name of `Memory::embedding`.
+pub const DEFAULT_SLOT: &str = "content";
+
+/// Identifies the model an embedding came from. Vectors from different
+/// models are not comparable, so each slot of a store is pinned to one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EmbeddingModel {
+    pub id: String,
+    pub version: String,
+}
+
+impl EmbeddingModel {
+    pub fn new(id: impl Into<String>, version: impl Into<String>) -> Self {
+        Self { id: id.into(), version: version.into() }
+    }
+}
+
+impl std::fmt::Display for EmbeddingModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.id, self.version)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum MemoryType {
@@ -76,6 +112,18 @@ pub struct InboundEdge {
     pub created_at: DateTime<Utc>,
 }
 
+impl MemoryType {
+    /// Variant name, as it appears in the serialized `type` tag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryType::Episodic { .. } => "Episodic",
+            MemoryType::Semantic { .. } => "Semantic",
+            MemoryType::Procedural { .. } => "Procedural",
+            MemoryType::Emotional { .. } => "Emotional",
+        }
+    }
+}
+
 impl Memory {
     pub fn new(content: String, embedding: Vec<f32>, memory_type: MemoryType) -> Self {
         let now = Utc::now();
@@ -83,6 +131,9 @@ impl Memory {
             id: Uuid::now_v7(),
             content,
             embedding,
+            embeddings: HashMap::new(),
+            embedding_model: None,
+            embedding_models: HashMap::new(),
             memory_type,
             metadata: HashMap::new(),
             edges: Vec::new(),
@@ -93,4 +144,28 @@ impl Memory {
             decay_rate: 0.1, // Default decay
         }
     }
+
+    /// Embedding stored under `slot`; `DEFAULT_SLOT` is `embedding`.
+    pub fn vector(&self, slot: &str) -> Option<&[f32]> {
+        if slot == DEFAULT_SLOT {
+            Some(&self.embedding)
+        } else {
+            self.embeddings.get(slot).map(Vec::as_slice)
+        }
+    }
+
+    /// Model recorded for the embedding in `slot`.
+    pub fn model(&self, slot: &str) -> Option<&EmbeddingModel> {
+        if slot == DEFAULT_SLOT {
+            self.embedding_model.as_ref()
+        } else {
+            self.embedding_models.get(slot)
+        }
+    }
+
+    /// Iterates over every slot, starting with `DEFAULT_SLOT`.
+    pub fn vectors(&self) -> impl Iterator<Item = (&str, &[f32])> {
+        std::iter::once((DEFAULT_SLOT, self.embedding.as_slice()))
+            .chain(self.embeddings.iter().map(|(slot, v)| (slot.as_str(), v.as_slice())))
+    }
 }
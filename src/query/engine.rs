@@ -1,144 +1,1020 @@
+use crate::graph::{self, EdgeSource};
 use crate::index::VectorIndex;
-use crate::models::Memory;
-use crate::query::{Query, Filter};
-use crate::storage::StorageManager;
+use crate::models::{DEFAULT_SLOT, Memory};
+use crate::query::{
+    Expansion, Filter, Fusion, PathStep, Query, QueryResult, RankBy, RankFactor, Stage, Traverse, VectorSearch,
+};
+use crate::storage::{StorageManager, StorageReader};
 use anyhow::Result;
+use chrono::Utc;
 use uuid::Uuid;
 use std::collections::{HashSet, HashMap};
 
-pub struct QueryEngine<'a, V: VectorIndex> {
+/// Score multiplier per traversal hop when `Traverse.attenuation` is unset.
+const DEFAULT_HOP_ATTENUATION: f32 = 0.5;
+/// Expanded-set cap when `Traverse.max_nodes` is unset.
+const DEFAULT_MAX_NODES: usize = 1000;
+
+/// Ranked search hits plus the raw similarity of each vector hit.
+struct Hits {
+    ranked: Vec<(Uuid, f32)>,
+    vector_scores: HashMap<Uuid, f32>,
+}
+
+impl Hits {
+    fn vector_only(ranked: Vec<(Uuid, f32)>) -> Self {
+        let vector_scores = ranked.iter().copied().collect();
+        Self { ranked, vector_scores }
+    }
+}
+
+/// How a candidate entered the result set, before its memory is fetched.
+struct Reached {
+    score: f32,
+    vector_score: Option<f32>,
+    path: Vec<PathStep>,
+    stage: Stage,
+}
+
+pub struct QueryEngine<'a, V: VectorIndex + ?Sized> {
     storage: &'a StorageManager,
     vector_index: &'a V,
+    /// Indexes for named embedding slots other than `DEFAULT_SLOT`.
+    slot_indexes: HashMap<String, &'a V>,
+    /// Adjacency read by traversal instead of the storage snapshot.
+    graph: Option<&'a dyn EdgeSource>,
 }
 
-impl<'a, V: VectorIndex> QueryEngine<'a, V> {
+impl<'a, V: VectorIndex + ?Sized> QueryEngine<'a, V> {
     pub fn new(storage: &'a StorageManager, vector_index: &'a V) -> Self {
         Self {
             storage,
             vector_index,
+            slot_indexes: HashMap::new(),
+            graph: None,
         }
     }
 
-    pub fn execute(&self, query: Query) -> Result<Vec<Memory>> {
-        let mut candidates: HashSet<Uuid> = HashSet::new();
-        let mut scores: HashMap<Uuid, f32> = HashMap::new();
+    /// Registers the index searched when `VectorSearch.slot` names `slot`.
+    pub fn with_slot(mut self, slot: impl Into<String>, index: &'a V) -> Self {
+        self.slot_indexes.insert(slot.into(), index);
+        self
+    }
+
+    /// Traverses edges from `graph`, typically an `AdjacencyCache`, instead
+    /// of reading them from the storage snapshot.
+    pub fn with_graph(mut self, graph: &'a dyn EdgeSource) -> Self {
+        self.graph = Some(graph);
+        self
+    }
 
-        // Step 1: Vector Search (Primary Driver)
-        let mut vector_search_performed = false;
-        if let Some(search) = &query.search {
-             if let Some(embedding) = &search.vector.embedding {
-                 let limit = query.limit.unwrap_or(10);
-                 let results = self.vector_index.search(embedding, limit)?;
-                 for (id, score) in results {
-                     candidates.insert(id);
-                     scores.insert(id, score);
-                 }
-                 vector_search_performed = true;
-             }
+    fn index_for(&self, slot: Option<&str>) -> Result<&'a V> {
+        match slot {
+            None | Some(DEFAULT_SLOT) => Ok(self.vector_index),
+            Some(slot) => self.slot_indexes.get(slot)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("no vector index registered for slot `{slot}`")),
         }
+    }
 
-        // Fallback: If no vector search, load all (Naive for v0)
-        if !vector_search_performed {
-            let all_memories = self.storage.list_memories()?;
-            for mem in all_memories {
-                candidates.insert(mem.id);
-                scores.insert(mem.id, 1.0);
+    pub fn execute(&self, query: Query) -> Result<Vec<QueryResult>> {
+        let reader = self.storage.read()?;
+
+        // Step 1: Filter (Pre-filtering)
+        // Resolve the allowed set first so a selective filter cannot starve
+        // the vector search of results.
+        let allowed = match &query.filter {
+            Some(filter) => Some(self.allowed_ids(&reader.list_memories()?, filter)?),
+            None => None,
+        };
+
+        // Step 2: Vector and/or text search (Primary Driver), restricted to the allowed set
+        let hits = self.search_hits(&reader, &query, allowed.as_ref())?;
+
+        self.expand_and_rank(&reader, &query, allowed, hits)
+    }
+
+    /// Runs many queries against one storage snapshot.
+    ///
+    /// Filters share a single scan of the memories table, and plain top-k
+    /// vector searches on the same slot and limit go through one
+    /// `search_batch` call.
+    /// Results are returned in query order.
+    pub fn execute_many(&self, queries: Vec<Query>) -> Result<Vec<Vec<QueryResult>>> {
+        let reader = self.storage.read()?;
+
+        let memories = if queries.iter().any(|q| q.filter.is_some()) {
+            reader.list_memories()?
+        } else {
+            Vec::new()
+        };
+        let allowed: Vec<Option<HashSet<Uuid>>> = queries.iter()
+            .map(|q| q.filter.as_ref().map(|filter| self.allowed_ids(&memories, filter)).transpose())
+            .collect::<Result<_>>()?;
+
+        let mut hits: Vec<Option<Hits>> = queries.iter().map(|_| None).collect();
+        let mut batches: HashMap<(&str, usize), Vec<usize>> = HashMap::new();
+        for (i, query) in queries.iter().enumerate() {
+            let batchable = query.search.as_ref()
+                .filter(|search| search.text.is_none() && allowed[i].is_none())
+                .and_then(|search| search.vector.as_ref())
+                .filter(|vector| vector.embedding.is_some() && !vector.radius);
+            match batchable {
+                Some(vector) => {
+                    // An unset slot is the default one, so both share a batch.
+                    let key = (vector.slot.as_deref().unwrap_or(DEFAULT_SLOT), query.limit.unwrap_or(10));
+                    batches.entry(key).or_default().push(i);
+                }
+                None => hits[i] = self.search_hits(&reader, query, allowed[i].as_ref())?,
             }
         }
 
-        // Step 2: Filter (Post-filtering)
-        if let Some(filter) = &query.filter {
-            let mut filtered_candidates = HashSet::new();
-            for id in &candidates {
-                if let Some(mem) = self.storage.get_memory(*id)? {
-                    if self.matches_filter(&mem, filter) {
-                        filtered_candidates.insert(*id);
-                    }
+        for ((slot, limit), members) in batches {
+            let index = self.index_for(Some(slot))?;
+            let vector_of = |i: usize| queries[i].search.as_ref().and_then(|s| s.vector.as_ref());
+            let vectors: Vec<Vec<f32>> = members.iter()
+                .filter_map(|&i| vector_of(i)?.embedding.clone())
+                .collect();
+            for (&i, mut results) in members.iter().zip(index.search_batch(&vectors, limit)?) {
+                if let Some(min_score) = vector_of(i).and_then(|v| v.threshold) {
+                    results.retain(|(_, score)| *score >= min_score);
                 }
+                hits[i] = Some(Hits::vector_only(results));
             }
-            candidates = filtered_candidates;
         }
 
-        // Step 3: Traversal (Graph Expansion)
-        if let Some(traverse) = &query.traverse {
-            let mut expanded_candidates = candidates.clone();
-            for id in &candidates {
-                // Default to outbound for now if not specified or "outbound"
-                if traverse.direction == "outbound" || traverse.direction == "both" {
-                    let edges = self.storage.get_outbound_edges(*id)?;
-                    for edge in edges {
-                        // TODO: Check edge types
-                        expanded_candidates.insert(edge.target_id);
-                        // Decay score for hops (simple heuristic)
-                        let parent_score = *scores.get(id).unwrap_or(&1.0);
-                        scores.entry(edge.target_id).or_insert(parent_score * 0.5); 
-                    }
+        queries.iter()
+            .zip(allowed)
+            .zip(hits)
+            .map(|((query, allowed), hits)| self.expand_and_rank(&reader, query, allowed, hits))
+            .collect()
+    }
+
+    fn allowed_ids(&self, memories: &[Memory], filter: &Filter) -> Result<HashSet<Uuid>> {
+        // Graph-constrained filters are reserved but not implemented; running
+        // the query unfiltered would silently widen its results.
+        if filter.criteria.get("graph_reachability").is_some() {
+            anyhow::bail!("unsupported filter `graph_reachability`: graph-constrained filters are not implemented");
+        }
+        Ok(memories.iter()
+            .filter(|mem| self.matches_filter(mem, filter))
+            .map(|mem| mem.id)
+            .collect())
+    }
+
+    /// Ranked hits for `query.search`, or `None` if it runs neither a vector
+    /// nor a text search. When both run, the two rankings are fused.
+    fn search_hits(&self, reader: &StorageReader, query: &Query, allowed: Option<&HashSet<Uuid>>) -> Result<Option<Hits>> {
+        let Some(search) = &query.search else {
+            return Ok(None);
+        };
+        let limit = query.limit.unwrap_or(10);
+
+        let vector_hits = match &search.vector {
+            Some(vector) => self.vector_search(vector, limit, allowed)?,
+            None => None,
+        };
+        let text_hits = match &search.text {
+            Some(text) => Some(match allowed {
+                Some(allowed) => reader.search_text(&text.query, limit, &|id| allowed.contains(&id))?,
+                None => reader.search_text(&text.query, limit, &|_| true)?,
+            }),
+            None => None,
+        };
+
+        Ok(match (vector_hits, text_hits) {
+            (Some(vector_hits), Some(text_hits)) => Some(Hits {
+                ranked: fuse(&vector_hits, &text_hits, search.fusion.unwrap_or_default()),
+                vector_scores: vector_hits.into_iter().collect(),
+            }),
+            (Some(vector_hits), None) => Some(Hits::vector_only(vector_hits)),
+            (None, Some(text_hits)) => Some(Hits { ranked: text_hits, vector_scores: HashMap::new() }),
+            (None, None) => None,
+        })
+    }
+
+    /// Vector hits for `vector`, or `None` if it carries no embedding.
+    fn vector_search(&self, vector: &VectorSearch, limit: usize, allowed: Option<&HashSet<Uuid>>) -> Result<Option<Vec<(Uuid, f32)>>> {
+        let Some(embedding) = &vector.embedding else {
+            return Ok(None);
+        };
+
+        let index = self.index_for(vector.slot.as_deref())?;
+        let threshold = vector.threshold;
+        let results = if vector.radius {
+            // Radius mode: every memory within the cutoff, not just the top `limit`.
+            let Some(min_score) = threshold else {
+                anyhow::bail!("radius search requires a threshold");
+            };
+            match allowed {
+                Some(allowed) => index.search_range_filtered(embedding, min_score, &|id| allowed.contains(&id))?,
+                None => index.search_range(embedding, min_score)?,
+            }
+        } else {
+            let mut hits = match allowed {
+                Some(allowed) => index.search_filtered(embedding, limit, &|id| allowed.contains(&id))?,
+                None => index.search(embedding, limit)?,
+            };
+            // Drop weak matches before they seed graph expansion.
+            if let Some(min_score) = threshold {
+                hits.retain(|(_, score)| *score >= min_score);
+            }
+            hits
+        };
+        Ok(Some(results))
+    }
+
+    fn expand_and_rank(
+        &self,
+        reader: &StorageReader,
+        query: &Query,
+        allowed: Option<HashSet<Uuid>>,
+        hits: Option<Hits>,
+    ) -> Result<Vec<QueryResult>> {
+        let mut reached: HashMap<Uuid, Reached> = HashMap::new();
+
+        match hits {
+            Some(hits) => {
+                for (id, score) in hits.ranked {
+                    let vector_score = hits.vector_scores.get(&id).copied();
+                    reached.insert(id, Reached { score, vector_score, path: Vec::new(), stage: Stage::Search });
                 }
-                
-                if traverse.direction == "inbound" || traverse.direction == "both" {
-                     let edges = self.storage.get_inbound_edges(*id)?;
-                     for edge in edges {
-                        expanded_candidates.insert(edge.source_id);
-                        let parent_score = *scores.get(id).unwrap_or(&1.0);
-                        scores.entry(edge.source_id).or_insert(parent_score * 0.5);
-                     }
+            }
+            // Fallback: If no vector search, take every allowed memory (Naive for v0)
+            None => {
+                let (ids, stage): (Vec<Uuid>, _) = match allowed {
+                    Some(allowed) => (allowed.into_iter().collect(), Stage::Filter),
+                    None => (reader.list_memories()?.into_iter().map(|mem| mem.id).collect(), Stage::Scan),
+                };
+                for id in ids {
+                    reached.insert(id, Reached { score: 1.0, vector_score: None, path: Vec::new(), stage });
                 }
             }
-            candidates = expanded_candidates;
+        }
+
+        // Step 3: Traversal (Graph Expansion)
+        if let Some(traverse) = &query.traverse {
+            let graph = self.graph.unwrap_or(reader);
+            match traverse.mode.unwrap_or_default() {
+                Expansion::BreadthFirst => Self::expand_breadth_first(graph, traverse, &mut reached)?,
+                Expansion::Activation => Self::spread_activation(graph, traverse, &mut reached)?,
+            }
         }
 
         // Step 4: Fetch, Sort and Return
-        let mut result_memories = Vec::new();
-        for id in candidates {
-            if let Some(mem) = self.storage.get_memory(id)? {
-                result_memories.push(mem);
+        let mut results = Vec::new();
+        for (id, found) in reached {
+            if let Some(memory) = reader.get_memory(id)? {
+                results.push(QueryResult {
+                    memory,
+                    score: found.score,
+                    vector_score: found.vector_score,
+                    hops: found.path.len() as u32,
+                    path: found.path,
+                    stage: found.stage,
+                });
             }
         }
-        
+
+        // Cognitive ranking, when the query overrides the pipeline score
+        if let Some(rank_by) = &query.rank_by {
+            self.rerank(reader, query, rank_by, &mut results)?;
+        }
+
         // Sort by score descending
-        result_memories.sort_by(|a, b| {
-            let score_a = scores.get(&a.id).unwrap_or(&0.0);
-            let score_b = scores.get(&b.id).unwrap_or(&0.0);
-            score_b.partial_cmp(score_a).unwrap_or(std::cmp::Ordering::Equal)
-        });
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.memory.id.cmp(&b.memory.id)));
 
         // Apply limit
         if let Some(limit) = query.limit {
-            if result_memories.len() > limit {
-                result_memories.truncate(limit);
+            results.truncate(limit);
+        }
+
+        Ok(results)
+    }
+
+    /// Replaces each result's score with the `rank_by` weighted sum of its
+    /// scoring factors.
+    fn rerank(&self, reader: &StorageReader, query: &Query, rank_by: &RankBy, results: &mut [QueryResult]) -> Result<()> {
+        // Similarity is recomputed for every result, so text and traversal
+        // hits are compared to the query vector on the same scale as search hits.
+        let vector = query.search.as_ref().and_then(|search| search.vector.as_ref());
+        let similarity_to = match vector.and_then(|v| v.embedding.as_deref().map(|e| (e, v.slot.as_deref()))) {
+            Some((embedding, slot)) => Some((embedding, slot.unwrap_or(DEFAULT_SLOT), self.index_for(slot)?.metric())),
+            None => None,
+        };
+
+        let now = Utc::now();
+        let max_access = results.iter().map(|r| r.memory.access_count).max().unwrap_or(0);
+        for result in results.iter_mut() {
+            let memory = &result.memory;
+            let mut score = 0.0;
+            for (factor, weight) in &rank_by.weights {
+                let value = match factor {
+                    RankFactor::VectorSimilarity => similarity_to
+                        .and_then(|(embedding, slot, metric)| {
+                            let stored = memory.vector(slot).filter(|v| v.len() == embedding.len())?;
+                            Some(metric.score(embedding, stored))
+                        })
+                        .unwrap_or(0.0),
+                    RankFactor::Importance => memory.importance,
+                    RankFactor::Recency => {
+                        let days = (now - memory.last_accessed_at).num_seconds().max(0) as f32 / 86_400.0;
+                        (-memory.decay_rate * days).exp()
+                    }
+                    RankFactor::AccessCount if max_access == 0 => 0.0,
+                    RankFactor::AccessCount => {
+                        (memory.access_count as f32).ln_1p() / (max_access as f32).ln_1p()
+                    }
+                    RankFactor::GraphCentrality => {
+                        reader.get_centrality(memory.id)?.map_or(0.0, |c| c.relative_pagerank)
+                    }
+                };
+                score += weight * value;
             }
+            result.score = score;
         }
+        Ok(())
+    }
+
+    /// Breadth-first from the seeds, one attenuation step per hop. A memory
+    /// keeps the score and path of the first hop that reaches it, the best
+    /// parent winning within a hop.
+    fn expand_breadth_first(edges: &dyn EdgeSource, traverse: &Traverse, reached: &mut HashMap<Uuid, Reached>) -> Result<()> {
+        let depth = traverse.depth.unwrap_or(1);
+        let attenuation = traverse.attenuation.unwrap_or(DEFAULT_HOP_ATTENUATION);
+        let max_nodes = traverse.max_nodes.unwrap_or(DEFAULT_MAX_NODES);
 
-        Ok(result_memories)
+        let mut frontier: Vec<Uuid> = reached.keys().copied().collect();
+        'expand: for _ in 0..depth {
+            // Strongest parents first, so the budget keeps the best paths.
+            frontier.sort_by(|a, b| reached[b].score.total_cmp(&reached[a].score).then(a.cmp(b)));
+            let mut next: HashMap<Uuid, Reached> = HashMap::new();
+            for id in &frontier {
+                for (step, multiplier) in Self::neighbors(edges, *id, traverse)? {
+                    let parent = &reached[id];
+                    let score = parent.score * attenuation * multiplier;
+                    let neighbor = step.to;
+                    if reached.contains_key(&neighbor) {
+                        continue;
+                    }
+                    if let Some(existing) = next.get(&neighbor)
+                        && existing.score >= score
+                    {
+                        continue;
+                    }
+                    if !next.contains_key(&neighbor) && reached.len() + next.len() >= max_nodes {
+                        reached.extend(next);
+                        break 'expand;
+                    }
+                    let mut path = parent.path.clone();
+                    path.push(step);
+                    next.insert(neighbor, Reached { score, vector_score: None, path, stage: Stage::Traversal });
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next.keys().copied().collect();
+            reached.extend(next);
+        }
+        Ok(())
     }
 
-    fn matches_filter(&self, memory: &Memory, filter: &Filter) -> bool {
-        // 1. Check ID
-        if let Some(id_val) = filter.criteria.get("id") {
-            if let Some(id_str) = id_val.as_str() {
-                if let Ok(filter_id) = Uuid::parse_str(id_str) {
-                    if memory.id != filter_id {
-                        return false;
+    /// Spreading activation from the seeds, which start with their search
+    /// score. For `depth` iterations every memory that received activation in
+    /// the previous one passes `attenuation` of it on, split across its
+    /// followed edges in proportion to `Edge.weight` and scaled by the
+    /// relation's multiplier. A memory's score is all the activation it
+    /// accumulated, seeds included; its path is the one activation first
+    /// reached it by.
+    ///
+    /// Without relation multipliers this is personalized PageRank truncated
+    /// after `depth` steps, with `attenuation` as the damping factor.
+    fn spread_activation(edges: &dyn EdgeSource, traverse: &Traverse, reached: &mut HashMap<Uuid, Reached>) -> Result<()> {
+        let iterations = traverse.depth.unwrap_or(1);
+        let decay = traverse.attenuation.unwrap_or(DEFAULT_HOP_ATTENUATION);
+        let max_nodes = traverse.max_nodes.unwrap_or(DEFAULT_MAX_NODES);
+
+        let mut pulses: Vec<(Uuid, f32)> = reached.iter().map(|(id, found)| (*id, found.score)).collect();
+        for _ in 0..iterations {
+            // Strongest pulses first, so the budget and first paths favour them.
+            pulses.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            let mut next: HashMap<Uuid, f32> = HashMap::new();
+            for (id, pulse) in pulses {
+                let steps: Vec<(PathStep, f32)> = Self::neighbors(edges, id, traverse)?
+                    .into_iter()
+                    .filter(|(step, _)| step.weight > 0.0)
+                    .collect();
+                let total_weight: f32 = steps.iter().map(|(step, _)| step.weight).sum();
+                for (step, multiplier) in steps {
+                    let share = pulse * decay * multiplier * step.weight / total_weight;
+                    if share <= 0.0 {
+                        continue;
                     }
+                    let neighbor = step.to;
+                    let full = reached.len() >= max_nodes;
+                    match reached.get_mut(&neighbor) {
+                        Some(found) => found.score += share,
+                        None if full => continue,
+                        None => {
+                            let mut path = reached[&id].path.clone();
+                            path.push(step);
+                            reached.insert(neighbor, Reached { score: share, vector_score: None, path, stage: Stage::Traversal });
+                        }
+                    }
+                    *next.entry(neighbor).or_default() += share;
                 }
             }
+            if next.is_empty() {
+                break;
+            }
+            pulses = next.into_iter().collect();
+        }
+        Ok(())
+    }
+
+    /// Edges one hop from `id` over the relation types `traverse` allows,
+    /// oriented away from `id`, with each relation's score multiplier.
+    fn neighbors(edges: &dyn EdgeSource, id: Uuid, traverse: &Traverse) -> Result<Vec<(PathStep, f32)>> {
+        let follows = |relation: &str| {
+            graph::follows(traverse.edge_types.as_deref(), traverse.exclude_edge_types.as_deref(), relation)
+        };
+        let multiplier = |relation: &str| {
+            traverse.edge_weights.as_ref().and_then(|weights| weights.get(relation)).copied().unwrap_or(1.0)
+        };
+
+        Ok(graph::steps(edges, id, traverse.direction, follows)?
+            .into_iter()
+            .map(|step| {
+                let factor = multiplier(&step.relation_type);
+                (step, factor)
+            })
+            .collect())
+    }
+
+    fn matches_filter(&self, memory: &Memory, filter: &Filter) -> bool {
+        // 1. Check ID
+        let filter_id = filter.criteria.get("id")
+            .and_then(|id_val| id_val.as_str())
+            .and_then(|id_str| Uuid::parse_str(id_str).ok());
+        if filter_id.is_some_and(|filter_id| memory.id != filter_id) {
+            return false;
         }
         
         // 2. Check Memory Type (simple string match on the enum variant name if possible, 
         // or just check if the JSON representation contains the type)
         // For now, let's assume the user passes "memory_type": "Episodic" in criteria
-        if let Some(type_val) = filter.criteria.get("memory_type") {
-             if let Some(type_str) = type_val.as_str() {
-                 let mem_json = serde_json::to_value(&memory.memory_type).unwrap();
-                 // mem_json is {"type": "Episodic", "data": {...}}
-                 if let Some(actual_type) = mem_json.get("type") {
-                     if actual_type.as_str() != Some(type_str) {
-                         return false;
-                     }
-                 }
-             }
+        if let Some(type_str) = filter.criteria.get("memory_type").and_then(|type_val| type_val.as_str()) {
+            let mem_json = serde_json::to_value(&memory.memory_type).unwrap();
+            // mem_json is {"type": "Episodic", "data": {...}}
+            if mem_json.get("type").is_some_and(|actual_type| actual_type.as_str() != Some(type_str)) {
+                return false;
+            }
+        }
+
+        // 3. Any other key is an equality match on metadata (e.g. "user_id": "alice").
+        // `graph_reachability` is rejected up front by `allowed_ids`.
+        for (key, expected) in filter.criteria.as_object().into_iter().flatten() {
+            if matches!(key.as_str(), "id" | "memory_type" | "graph_reachability") {
+                continue;
+            }
+            if memory.metadata.get(key) != Some(expected) {
+                return false;
+            }
         }
 
         true
     }
 }
+
+/// Combines two rankings (each best first) into one, best first.
+///
+/// Cosine similarities and BM25 scores live on different scales, so neither
+/// method adds raw scores: RRF uses only ranks, and the weighted sum rescales
+/// each list to 0..1 first. A memory missing from a list gets nothing from it.
+fn fuse(vector_hits: &[(Uuid, f32)], text_hits: &[(Uuid, f32)], fusion: Fusion) -> Vec<(Uuid, f32)> {
+    let mut fused: HashMap<Uuid, f32> = HashMap::new();
+    match fusion {
+        Fusion::Rrf { k } => {
+            for hits in [vector_hits, text_hits] {
+                for (rank, (id, _)) in hits.iter().enumerate() {
+                    *fused.entry(*id).or_insert(0.0) += 1.0 / (k + rank as f32 + 1.0);
+                }
+            }
+        }
+        Fusion::Weighted { vector_weight, text_weight } => {
+            for (hits, weight) in [(vector_hits, vector_weight), (text_hits, text_weight)] {
+                let max = hits.iter().map(|(_, s)| *s).fold(f32::NEG_INFINITY, f32::max);
+                let min = hits.iter().map(|(_, s)| *s).fold(f32::INFINITY, f32::min);
+                for (id, score) in hits {
+                    let normalized = if max > min { (score - min) / (max - min) } else { 1.0 };
+                    *fused.entry(*id).or_insert(0.0) += weight * normalized;
+                }
+            }
+        }
+    }
+
+    let mut fused: Vec<(Uuid, f32)> = fused.into_iter().collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::flat::FlatIndex;
+    use crate::index::{Metric, persist};
+    use crate::query::Direction;
+    use crate::models::MemoryType;
+    use crate::storage::TempStorage;
+
+    fn fact(content: &str, embedding: Vec<f32>) -> Memory {
+        Memory::new(content.to_string(), embedding, MemoryType::Semantic { confidence: 1.0, source: "test".to_string() })
+    }
+
+    #[test]
+    fn vector_search_uses_the_index_of_its_slot() {
+        let storage = TempStorage::new();
+        let mut dust = fact("dust storm", vec![1.0, 0.0]);
+        dust.embeddings.insert("summary".to_string(), vec![0.0, 1.0]);
+        let mut solar = fact("solar array", vec![0.0, 1.0]);
+        solar.embeddings.insert("summary".to_string(), vec![1.0, 0.0]);
+        storage.save_memory(&dust).unwrap();
+        storage.save_memory(&solar).unwrap();
+        let content = persist::load_or_rebuild(&storage, DEFAULT_SLOT, FlatIndex::new).unwrap();
+        let summary = persist::load_or_rebuild(&storage, "summary", FlatIndex::new).unwrap();
+        let engine = QueryEngine::new(&storage, &content).with_slot("summary", &summary);
+
+        let top = |slot: serde_json::Value| -> Result<Uuid> {
+            let query: Query = serde_json::from_value(serde_json::json!({
+                "search": { "vector": { "embedding": [1.0, 0.0], "slot": slot } },
+                "limit": 1,
+            })).unwrap();
+            Ok(engine.execute(query)?[0].memory.id)
+        };
+        assert_eq!(top(serde_json::Value::Null).unwrap(), dust.id);
+        assert_eq!(top(DEFAULT_SLOT.into()).unwrap(), dust.id);
+        assert_eq!(top("summary".into()).unwrap(), solar.id);
+        let err = top("emotion".into()).unwrap_err();
+        assert!(err.to_string().contains("no vector index registered for slot `emotion`"), "{err}");
+    }
+
+    #[test]
+    fn threshold_and_radius_cut_on_distance_scores() {
+        // Memory `i` sits at (i, i): L2 distance i * sqrt(2), L1 distance 2i.
+        let storage = TempStorage::new();
+        let memories: Vec<Memory> = (0..40).map(|i| fact(&format!("marker {i}"), vec![i as f32, i as f32])).collect();
+        for memory in &memories {
+            storage.save_memory(memory).unwrap();
+        }
+
+        for (metric, near, far) in [(Metric::Euclidean, 7, 35), (Metric::Manhattan, 5, 25)] {
+            let mut index = FlatIndex::new().with_metric(metric);
+            for memory in &memories {
+                index.add(memory.id, &memory.embedding).unwrap();
+            }
+            let engine = QueryEngine::new(&storage, &index);
+            let run = |vector: serde_json::Value, limit: Option<usize>| -> Vec<QueryResult> {
+                let query: Query = serde_json::from_value(serde_json::json!({
+                    "search": { "vector": vector },
+                    "limit": limit,
+                })).unwrap();
+                engine.execute(query).unwrap()
+            };
+            let expected = |count: usize| memories[..count].iter().map(|m| m.id).collect::<HashSet<_>>();
+            let ids = |results: &[QueryResult]| results.iter().map(|r| r.memory.id).collect::<HashSet<_>>();
+
+            // `limit` still caps a thresholded search; the cutoff drops the rest.
+            let results = run(serde_json::json!({ "embedding": [0.0, 0.0], "threshold": 0.1 }), Some(20));
+            assert_eq!(ids(&results), expected(near), "{metric:?}");
+            assert!(results.iter().all(|r| r.vector_score.is_some_and(|score| score >= 0.1)));
+            let results = run(serde_json::json!({ "embedding": [0.0, 0.0], "threshold": 0.1 }), Some(3));
+            assert_eq!(ids(&results), expected(3), "{metric:?}");
+
+            // Radius mode ignores the default limit of 10.
+            let results = run(serde_json::json!({ "embedding": [0.0, 0.0], "threshold": 0.02, "radius": true }), None);
+            assert_eq!(ids(&results), expected(far), "{metric:?}");
+            let err = engine.execute(serde_json::from_value(serde_json::json!({
+                "search": { "vector": { "embedding": [0.0, 0.0], "radius": true } },
+            })).unwrap()).unwrap_err();
+            assert!(err.to_string().contains("radius search requires a threshold"), "{err}");
+        }
+    }
+
+    /// A `FlatIndex` that counts `search_batch` calls.
+    struct CountingIndex {
+        inner: FlatIndex,
+        batches: std::cell::Cell<usize>,
+    }
+
+    impl VectorIndex for CountingIndex {
+        fn add(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+            self.inner.add(id, vector)
+        }
+
+        fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(Uuid, f32)>> {
+            self.inner.search(vector, k)
+        }
+
+        fn search_filtered(&self, vector: &[f32], k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+            self.inner.search_filtered(vector, k, allowed)
+        }
+
+        fn search_batch(&self, vectors: &[Vec<f32>], k: usize) -> Result<Vec<Vec<(Uuid, f32)>>> {
+            self.batches.set(self.batches.get() + 1);
+            self.inner.search_batch(vectors, k)
+        }
+
+        fn search_range_filtered(&self, vector: &[f32], min_score: f32, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+            self.inner.search_range_filtered(vector, min_score, allowed)
+        }
+
+        fn remove(&mut self, id: Uuid) -> Result<bool> {
+            self.inner.remove(id)
+        }
+
+        fn contains(&self, id: Uuid) -> bool {
+            self.inner.contains(id)
+        }
+
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        fn metric(&self) -> Metric {
+            self.inner.metric()
+        }
+
+        fn dimension(&self) -> Option<usize> {
+            self.inner.dimension()
+        }
+    }
+
+    #[test]
+    fn execute_many_matches_executing_each_query() {
+        let storage = TempStorage::new();
+        let words = ["dust", "storm", "solar", "array", "oxygen", "scrubber", "rover", "battery"];
+        let memories: Vec<Memory> = (0..24)
+            .map(|i| {
+                let angle = i as f32 * 0.13;
+                let mut memory = fact(&format!("{} {}", words[i % 8], words[(i * 3 + 1) % 8]), vec![angle.cos(), angle.sin()]);
+                memory.embeddings.insert("summary".to_string(), vec![angle.sin(), angle.cos()]);
+                memory
+            })
+            .collect();
+        for memory in &memories {
+            storage.save_memory(memory).unwrap();
+        }
+        for pair in memories.windows(2) {
+            storage.add_edge_inherent(pair[0].id, pair[1].id, "follows".to_string(), 0.8).unwrap();
+        }
+        let index = |slot: &str| CountingIndex {
+            inner: persist::load_or_rebuild(&storage, slot, FlatIndex::new).unwrap(),
+            batches: std::cell::Cell::new(0),
+        };
+        let (content, summary) = (index(DEFAULT_SLOT), index("summary"));
+        let engine = QueryEngine::new(&storage, &content).with_slot("summary", &summary);
+
+        let queries = || -> Vec<Query> {
+            [
+                serde_json::json!({ "search": { "vector": { "embedding": [1.0, 0.0] } }, "limit": 3 }),
+                serde_json::json!({ "search": { "vector": { "embedding": [0.0, 1.0], "slot": DEFAULT_SLOT } }, "limit": 3 }),
+                serde_json::json!({ "search": { "vector": { "embedding": [0.6, 0.8], "threshold": 0.99 } }, "limit": 3 }),
+                serde_json::json!({ "search": { "vector": { "embedding": [0.6, 0.8] } } }),
+                serde_json::json!({ "search": { "vector": { "embedding": [0.8, 0.6], "slot": "summary" } }, "limit": 2 }),
+                serde_json::json!({
+                    "filter": { "id": memories[5].id.to_string() },
+                    "search": { "vector": { "embedding": [1.0, 0.0] } },
+                }),
+                serde_json::json!({ "search": { "text": { "query": "solar battery" } }, "limit": 4 }),
+                serde_json::json!({
+                    "search": { "vector": { "embedding": [1.0, 0.0] }, "text": { "query": "rover" } },
+                    "limit": 5,
+                }),
+                serde_json::json!({ "search": { "vector": { "embedding": [0.0, 1.0], "threshold": 0.98, "radius": true } } }),
+                serde_json::json!({
+                    "search": { "vector": { "embedding": [1.0, 0.0] } },
+                    "traverse": { "direction": "outbound", "edge_types": null, "depth": 2 },
+                    "limit": 3,
+                }),
+                serde_json::json!({ "filter": { "memory_type": "Semantic" }, "limit": 4 }),
+            ]
+            .into_iter()
+            .map(|query| serde_json::from_value(query).unwrap())
+            .collect()
+        };
+
+        let one_by_one = queries().into_iter().map(|query| engine.execute(query)).collect::<Result<Vec<_>>>().unwrap();
+        content.batches.set(0);
+        let batched = engine.execute_many(queries()).unwrap();
+        assert_eq!(serde_json::to_value(&batched).unwrap(), serde_json::to_value(&one_by_one).unwrap());
+        assert!(batched.iter().all(|results| !results.is_empty()));
+
+        // Unset and explicit default slots at limit 3 share one batch, as do
+        // the two default-limit queries; the summary query runs on its own.
+        assert_eq!(content.batches.get(), 2);
+        assert_eq!(summary.batches.get(), 1);
+    }
+
+    #[test]
+    fn graph_reachability_filter_is_rejected() {
+        let storage = TempStorage::new();
+        let memory = fact("dust storm", vec![1.0, 0.0]);
+        storage.save_memory(&memory).unwrap();
+        let mut index = FlatIndex::new();
+        index.add(memory.id, &memory.embedding).unwrap();
+        let engine = QueryEngine::new(&storage, &index);
+
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "filter": { "graph_reachability": { "start_node": memory.id, "max_depth": 2 } },
+            "search": { "vector": { "embedding": [1.0, 0.0] } },
+        })).unwrap();
+        let err = engine.execute(query).unwrap_err();
+        assert!(err.to_string().contains("unsupported filter"), "{err}");
+
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "filter": { "memory_type": "Semantic" },
+            "search": { "vector": { "embedding": [1.0, 0.0] } },
+        })).unwrap();
+        assert_eq!(engine.execute(query).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn vector_similarity_factor_scores_traversal_hits_against_the_query() {
+        let storage = TempStorage::new();
+        let seed = fact("oxygen alarm", vec![1.0, 0.0]);
+        let linked = fact("scrubber fault", vec![0.6, 0.8]);
+        storage.save_memory(&seed).unwrap();
+        storage.save_memory(&linked).unwrap();
+        storage.add_edge_inherent(seed.id, linked.id, "causes".to_string(), 1.0).unwrap();
+        let mut index = FlatIndex::new();
+        index.add(seed.id, &seed.embedding).unwrap();
+        index.add(linked.id, &linked.embedding).unwrap();
+        let engine = QueryEngine::new(&storage, &index);
+
+        let query = |rank_by: serde_json::Value| -> Query {
+            serde_json::from_value(serde_json::json!({
+                "search": { "vector": { "embedding": [1.0, 0.0], "threshold": 0.9 } },
+                "traverse": { "direction": "outbound", "edge_types": null, "depth": 1 },
+                "rank_by": rank_by,
+            })).unwrap()
+        };
+        // Only the seed is a search hit; the linked memory is reached by traversal.
+        let results = engine.execute(query(serde_json::json!({ "weights": { "vector_similarity": 1.0 } }))).unwrap();
+        assert_eq!(results.len(), 2);
+        let linked_result = results.iter().find(|r| r.memory.id == linked.id).unwrap();
+        assert_eq!(linked_result.stage, Stage::Traversal);
+        assert!((linked_result.score - 0.6).abs() < 1e-6, "{}", linked_result.score);
+
+        // The earlier formula form ranks the same way.
+        let results = engine.execute(query(serde_json::json!({ "formula": "vector_similarity" }))).unwrap();
+        assert_eq!(results[0].memory.id, seed.id);
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+    }
+
+    /// Saves `n` memories; the first `seeds` are embedded at [1, 0] and the
+    /// rest at [0, 1], so a thresholded search finds only the seeds.
+    fn graph_of(storage: &StorageManager, n: usize, seeds: usize, edges: &[(usize, usize, &str, f32)]) -> Vec<Uuid> {
+        let memories: Vec<Memory> = (0..n)
+            .map(|i| fact(&format!("node {i}"), if i < seeds { vec![1.0, 0.0] } else { vec![0.0, 1.0] }))
+            .collect();
+        for memory in &memories {
+            storage.save_memory(memory).unwrap();
+        }
+        for &(source, target, relation, weight) in edges {
+            storage.add_edge_inherent(memories[source].id, memories[target].id, relation.to_string(), weight).unwrap();
+        }
+        memories.iter().map(|memory| memory.id).collect()
+    }
+
+    /// Runs `traverse` from the seeds of `graph_of` and returns the results by id.
+    fn expand(storage: &StorageManager, traverse: serde_json::Value) -> HashMap<Uuid, QueryResult> {
+        let index = persist::load_or_rebuild(storage, DEFAULT_SLOT, FlatIndex::new).unwrap();
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "search": { "vector": { "embedding": [1.0, 0.0], "threshold": 0.5 } },
+            "traverse": traverse,
+        })).unwrap();
+        QueryEngine::new(storage, &index).execute(query).unwrap()
+            .into_iter()
+            .map(|result| (result.memory.id, result))
+            .collect()
+    }
+
+    #[test]
+    fn breadth_first_expansion_stops_at_depth_and_attenuates_per_hop() {
+        let storage = TempStorage::new();
+        let ids = graph_of(&storage, 5, 1, &[(0, 1, "next", 1.0), (1, 2, "next", 1.0), (2, 3, "next", 1.0), (3, 4, "next", 1.0)]);
+
+        for depth in 1..=3 {
+            let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": depth }));
+            assert_eq!(results.len(), depth + 1, "depth {depth}");
+            for (hops, id) in ids[..=depth].iter().enumerate() {
+                assert_eq!(results[id].hops, hops as u32);
+                assert!((results[id].score - 0.5f32.powi(hops as i32)).abs() < 1e-6);
+            }
+        }
+
+        let results = expand(&storage, serde_json::json!({
+            "direction": "outbound", "edge_types": null, "depth": 4, "attenuation": 0.8,
+        }));
+        assert_eq!(results.len(), 5);
+        for (hops, id) in ids.iter().enumerate() {
+            assert!((results[id].score - 0.8f32.powi(hops as i32)).abs() < 1e-6);
+        }
+
+        // Inbound from the seed finds nothing on a chain that starts there.
+        let results = expand(&storage, serde_json::json!({ "direction": "inbound", "edge_types": null, "depth": 3 }));
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn breadth_first_expansion_visits_each_memory_once_on_cycles() {
+        let storage = TempStorage::new();
+        let ids = graph_of(&storage, 3, 1, &[(0, 1, "next", 1.0), (1, 2, "next", 1.0), (2, 0, "next", 1.0)]);
+
+        let results = expand(&storage, serde_json::json!({ "direction": "both", "edge_types": null, "depth": 10 }));
+        assert_eq!(results.len(), 3);
+        // The seed keeps its own score; both neighbours are one hop away undirected.
+        assert_eq!(results[&ids[0]].score, 1.0);
+        assert_eq!(results[&ids[0]].hops, 0);
+        assert_eq!(results[&ids[1]].hops, 1);
+        assert_eq!(results[&ids[2]].hops, 1);
+
+        let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": 10 }));
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[&ids[2]].hops, 2);
+        assert_eq!(results[&ids[0]].score, 1.0);
+    }
+
+    #[test]
+    fn breadth_first_expansion_respects_max_nodes() {
+        let storage = TempStorage::new();
+        let edges: Vec<(usize, usize, &str, f32)> = (1..6).map(|i| (0, i, "cites", 1.0)).chain([(1, 6, "cites", 1.0)]).collect();
+        let ids = graph_of(&storage, 7, 1, &edges);
+
+        let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": 2, "max_nodes": 3 }));
+        assert_eq!(results.len(), 3);
+        assert!(results.contains_key(&ids[0]));
+        assert!(results.values().all(|result| result.hops <= 1));
+
+        let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": 2, "max_nodes": 7 }));
+        assert_eq!(results.len(), 7);
+        let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": 2, "max_nodes": 1 }));
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn excluded_relations_are_skipped_and_edge_weights_scale_scores() {
+        let storage = TempStorage::new();
+        let ids = graph_of(&storage, 4, 1, &[(0, 1, "causes", 1.0), (0, 2, "mentions", 1.0), (2, 3, "causes", 1.0)]);
+        let traverse = |extra: serde_json::Value| {
+            let mut traverse = serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": 2 });
+            traverse.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            expand(&storage, traverse)
+        };
+
+        // Excluding `mentions` also cuts off what lies behind it.
+        let results = traverse(serde_json::json!({ "exclude_edge_types": ["mentions"] }));
+        assert_eq!(results.keys().collect::<HashSet<_>>(), HashSet::from([&ids[0], &ids[1]]));
+        // Exclusion wins over an explicit allow list.
+        let results = traverse(serde_json::json!({ "edge_types": ["causes", "mentions"], "exclude_edge_types": ["mentions"] }));
+        assert_eq!(results.len(), 2);
+        assert!(!results.contains_key(&ids[2]));
+
+        // Each hop multiplies in its relation's weight; unlisted relations use 1.
+        let results = traverse(serde_json::json!({ "edge_weights": { "causes": 1.6, "mentions": 0.2 } }));
+        assert_eq!(results.len(), 4);
+        assert!((results[&ids[1]].score - 0.5 * 1.6).abs() < 1e-6);
+        assert!((results[&ids[2]].score - 0.5 * 0.2).abs() < 1e-6);
+        assert!((results[&ids[3]].score - 0.5 * 0.2 * 0.5 * 1.6).abs() < 1e-6);
+        let results = traverse(serde_json::json!({ "edge_weights": { "mentions": 0.2 } }));
+        assert!((results[&ids[1]].score - 0.5).abs() < 1e-6);
+        assert!((results[&ids[3]].score - 0.5 * 0.2 * 0.5).abs() < 1e-6);
+
+        // Activation honours both the same way.
+        let results = traverse(serde_json::json!({ "mode": "activation", "exclude_edge_types": ["mentions"] }));
+        assert_eq!(results.len(), 2);
+        assert!((results[&ids[1]].score - 0.5).abs() < 1e-6);
+        let results = traverse(serde_json::json!({ "mode": "activation", "edge_weights": { "causes": 1.6, "mentions": 0.2 } }));
+        assert!((results[&ids[1]].score - 0.5 * 1.6 * 0.5).abs() < 1e-6);
+        assert!((results[&ids[2]].score - 0.5 * 0.2 * 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn results_record_their_stage_and_the_path_that_reached_them() {
+        let storage = TempStorage::new();
+        let edges = [(0, 1, "causes", 1.0), (0, 2, "mentions", 1.0), (1, 3, "causes", 0.9), (2, 3, "causes", 1.0), (4, 1, "observed", 0.7)];
+        let ids = graph_of(&storage, 5, 1, &edges);
+        let step = |from: usize, to: usize, relation: &str, weight: f32, direction: Direction| PathStep {
+            from: ids[from],
+            to: ids[to],
+            relation_type: relation.to_string(),
+            weight,
+            direction,
+        };
+
+        // `d` is two hops away either way; the stronger `causes` parent wins.
+        let results = expand(&storage, serde_json::json!({
+            "direction": "both", "edge_types": null, "depth": 2, "edge_weights": { "mentions": 0.5 },
+        }));
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[&ids[0]].stage, Stage::Search);
+        assert!(results[&ids[0]].path.is_empty());
+        assert_eq!(results[&ids[1]].path, [step(0, 1, "causes", 1.0, Direction::Outbound)]);
+        assert_eq!(results[&ids[3]].path, [step(0, 1, "causes", 1.0, Direction::Outbound), step(1, 3, "causes", 0.9, Direction::Outbound)]);
+        assert_eq!(results[&ids[3]].hops, 2);
+        // `observed` points at `b`, so reaching its source follows it backwards.
+        assert_eq!(results[&ids[4]].path, [step(0, 1, "causes", 1.0, Direction::Outbound), step(1, 4, "observed", 0.7, Direction::Inbound)]);
+        for id in &ids[1..] {
+            assert_eq!(results[id].stage, Stage::Traversal);
+            assert_eq!(results[id].hops as usize, results[id].path.len());
+        }
+
+        // Without a search, memories come from the filter or, lacking one, a scan.
+        let index = FlatIndex::new();
+        let engine = QueryEngine::new(&storage, &index);
+        let stages = |query: serde_json::Value| -> Vec<Stage> {
+            engine.execute(serde_json::from_value(query).unwrap()).unwrap().iter().map(|r| r.stage).collect()
+        };
+        assert_eq!(stages(serde_json::json!({})), [Stage::Scan; 5]);
+        assert_eq!(stages(serde_json::json!({ "filter": { "memory_type": "Semantic" } })), [Stage::Filter; 5]);
+        let filtered = stages(serde_json::json!({
+            "filter": { "id": ids[0].to_string() },
+            "traverse": { "direction": "outbound", "edge_types": null, "depth": 1 },
+        }));
+        assert_eq!(filtered, [Stage::Filter, Stage::Traversal, Stage::Traversal]);
+    }
+
+    #[test]
+    fn activation_accumulates_from_every_source_and_splits_by_weight() {
+        let storage = TempStorage::new();
+        // Seeds 0 and 1 both feed 2; seed 0 also feeds 3 with three times the weight.
+        let ids = graph_of(&storage, 4, 2, &[(0, 2, "cites", 1.0), (0, 3, "cites", 3.0), (1, 2, "cites", 1.0)]);
+        let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "mode": "activation" }));
+
+        assert_eq!(results.len(), 4);
+        assert!((results[&ids[3]].score - 0.5 * 0.75).abs() < 1e-6);
+        assert!((results[&ids[2]].score - (0.5 * 0.25 + 0.5)).abs() < 1e-6);
+        // The seeds keep their search scores, as nothing flows back to them.
+        assert_eq!(results[&ids[0]].score, 1.0);
+        assert_eq!(results[&ids[1]].score, 1.0);
+
+        // Activation returning round a cycle adds to the seed's own score.
+        let storage = TempStorage::new();
+        let ids = graph_of(&storage, 2, 1, &[(0, 1, "next", 1.0), (1, 0, "next", 1.0)]);
+        let results = expand(&storage, serde_json::json!({ "direction": "outbound", "edge_types": null, "depth": 2, "mode": "activation" }));
+        assert!((results[&ids[0]].score - 1.25).abs() < 1e-6);
+        assert!((results[&ids[1]].score - 0.5).abs() < 1e-6);
+        assert!(results[&ids[0]].path.is_empty());
+    }
+
+    #[test]
+    fn activation_decays_per_iteration_and_stops_when_nothing_flows() {
+        let storage = TempStorage::new();
+        let ids = graph_of(&storage, 5, 1, &[(0, 1, "next", 1.0), (1, 2, "next", 1.0), (2, 3, "next", 1.0), (1, 4, "next", 0.0)]);
+        let activation = |extra: serde_json::Value| {
+            let mut traverse = serde_json::json!({ "direction": "outbound", "edge_types": null, "mode": "activation" });
+            traverse.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            expand(&storage, traverse)
+        };
+
+        let results = activation(serde_json::json!({ "depth": 3, "attenuation": 0.4 }));
+        assert_eq!(results.len(), 4);
+        for (hops, id) in ids[..4].iter().enumerate() {
+            assert!((results[id].score - 0.4f32.powi(hops as i32)).abs() < 1e-6);
+        }
+        // Zero-weight edges carry nothing, so their targets are never reached.
+        assert!(!results.contains_key(&ids[4]));
+
+        // Fewer iterations stop short, and zero decay stops at the seeds.
+        assert_eq!(activation(serde_json::json!({ "depth": 2 })).len(), 3);
+        assert_eq!(activation(serde_json::json!({ "depth": 3, "attenuation": 0.0 })).len(), 1);
+        assert_eq!(activation(serde_json::json!({ "depth": 3, "max_nodes": 2 })).len(), 2);
+    }
+
+    #[test]
+    fn activation_matches_breadth_first_on_a_chain() {
+        // With one seed and one followed edge per memory, no activation is
+        // split or merged, so both modes score and route every memory alike.
+        let storage = TempStorage::new();
+        let ids = graph_of(&storage, 5, 1, &[(0, 1, "causes", 1.0), (1, 2, "causes", 1.0), (2, 3, "mentions", 1.0), (3, 4, "causes", 1.0)]);
+        for depth in [1, 3, 6] {
+            let traverse = |mode: &str| serde_json::json!({
+                "direction": "outbound", "edge_types": null, "depth": depth, "attenuation": 0.7,
+                "edge_weights": { "mentions": 0.5 }, "mode": mode,
+            });
+            let breadth_first = expand(&storage, traverse("breadth_first"));
+            let activation = expand(&storage, traverse("activation"));
+            assert_eq!(breadth_first.len(), activation.len(), "depth {depth}");
+            for id in &ids {
+                let Some(expected) = breadth_first.get(id) else {
+                    assert!(!activation.contains_key(id));
+                    continue;
+                };
+                assert!((activation[id].score - expected.score).abs() < 1e-6);
+                assert_eq!(activation[id].path, expected.path);
+                assert_eq!(activation[id].stage, expected.stage);
+            }
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use crate::models::Memory;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
 
 pub mod engine;
 
@@ -20,25 +23,227 @@ pub struct Filter {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Search {
-    pub vector: VectorSearch,
+    #[serde(default)]
+    pub vector: Option<VectorSearch>,
+    /// BM25 keyword search over `content`
+    #[serde(default)]
+    pub text: Option<TextSearch>,
+    /// How vector and text rankings are combined when both are given; defaults to RRF
+    #[serde(default)]
+    pub fusion: Option<Fusion>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TextSearch {
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Fusion {
+    /// Reciprocal rank fusion: each list contributes `1 / (k + rank)`.
+    Rrf {
+        #[serde(default = "default_rrf_k")]
+        k: f32,
+    },
+    /// Weighted sum of min-max normalised scores.
+    Weighted { vector_weight: f32, text_weight: f32 },
+}
+
+fn default_rrf_k() -> f32 {
+    60.0
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::Rrf { k: default_rrf_k() }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct VectorSearch {
     pub text: Option<String>,
     pub embedding: Option<Vec<f32>>,
+    /// Minimum similarity; weaker matches are dropped before traversal
     pub threshold: Option<f32>,
+    /// Return every memory scoring at least `threshold` instead of the top `limit`
+    #[serde(default)]
+    pub radius: bool,
+    /// Named embedding slot to search; defaults to `content`
+    #[serde(default)]
+    pub slot: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Traverse {
-    pub direction: String, // "inbound", "outbound", "both"
+    pub direction: Direction,
+    /// Only follow these relation types; all types when unset
     pub edge_types: Option<Vec<String>>,
+    /// Never follow these relation types, even if listed in `edge_types`
+    #[serde(default)]
+    pub exclude_edge_types: Option<Vec<String>>,
+    /// Extra score multiplier per relation type on top of `attenuation`; 1.0 when unlisted
+    #[serde(default)]
+    pub edge_weights: Option<HashMap<String, f32>>,
+    /// Hops to expand from the search hits; defaults to 1
     pub depth: Option<u32>,
+    /// Score multiplier applied per hop; defaults to 0.5
+    #[serde(default)]
+    pub attenuation: Option<f32>,
+    /// Cap on memories in the expanded set, seeds included; defaults to 1000
+    #[serde(default)]
+    pub max_nodes: Option<usize>,
+    /// How scores propagate; defaults to breadth-first
+    #[serde(default)]
+    pub mode: Option<Expansion>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expansion {
+    /// Each memory scores its best parent's score times `attenuation`, once
+    #[default]
+    BreadthFirst,
+    /// Spreading activation: `depth` iterations, `attenuation` as the decay,
+    /// activation split along edges by weight and accumulated per memory
+    Activation,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Follow `edges_out`, source to target
+    #[default]
+    Outbound,
+    /// Follow `edges_in`, target to source
+    Inbound,
+    Both,
 }
 
+/// Replaces each result's score with a weighted sum of scoring factors.
+///
+/// Queries written against the earlier `{"formula": "..."}` form still parse:
+/// a formula is a `+`-separated sum of `weight * factor` terms, e.g.
+/// `"0.7 * vector_similarity + 0.3 * importance"`, and is read into `weights`.
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(try_from = "RankSpec")]
 pub struct RankBy {
-    // Placeholder for custom ranking logic
-    pub formula: String,
+    pub weights: HashMap<RankFactor, f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RankSpec {
+    Weights { weights: HashMap<RankFactor, f32> },
+    Formula { formula: String },
+}
+
+impl TryFrom<RankSpec> for RankBy {
+    type Error = String;
+
+    fn try_from(spec: RankSpec) -> Result<Self, String> {
+        let formula = match spec {
+            RankSpec::Weights { weights } => return Ok(Self { weights }),
+            RankSpec::Formula { formula } => formula,
+        };
+        let mut weights = HashMap::new();
+        for term in formula.split('+').map(str::trim) {
+            let parts: Vec<&str> = term.split('*').map(str::trim).collect();
+            let (weight, name) = match parts[..] {
+                [name] => (1.0, name),
+                [a, b] => match (a.parse::<f32>(), b.parse::<f32>()) {
+                    (Ok(weight), Err(_)) => (weight, b),
+                    (Err(_), Ok(weight)) => (weight, a),
+                    _ => return Err(format!("rank_by formula term `{term}` needs one weight and one factor")),
+                },
+                _ => return Err(format!("rank_by formula term `{term}` is not `weight * factor`")),
+            };
+            let factor: RankFactor = serde_json::from_value(Value::String(name.to_string()))
+                .map_err(|_| format!("unknown rank_by factor `{name}`"))?;
+            *weights.entry(factor).or_insert(0.0) += weight;
+        }
+        Ok(Self { weights })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankFactor {
+    /// Similarity of the memory's vector in the searched slot to
+    /// `search.vector.embedding`, under that index's `Metric`; 0 without one
+    VectorSimilarity,
+    /// `Memory.importance`
+    Importance,
+    /// `exp(-decay_rate * days since last_accessed_at)`
+    Recency,
+    /// `ln(1 + access_count)`, relative to the most accessed result
+    AccessCount,
+    /// Stored `Centrality.relative_pagerank`; 0 until `graph::centrality` has run
+    GraphCentrality,
+}
+
+/// One recalled memory, with how it was found and scored.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueryResult {
+    pub memory: Memory,
+    /// Score the results are ordered by
+    pub score: f32,
+    /// Similarity from the vector search, for memories it returned
+    pub vector_score: Option<f32>,
+    /// Hops from the seed memory; 0 for seeds
+    pub hops: u32,
+    /// Edges followed from the seed, in order; empty for seeds
+    pub path: Vec<PathStep>,
+    pub stage: Stage,
+}
+
+/// One edge on a traversal path.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PathStep {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub relation_type: String,
+    pub weight: f32,
+    /// `Outbound` if the edge runs `from -> to`, `Inbound` if it was followed backwards
+    pub direction: Direction,
+}
+
+/// Pipeline stage that added a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Listed because the query had neither a filter nor a search
+    Scan,
+    /// Matched the filter, with no search to rank it
+    Filter,
+    /// Returned by the vector and/or text search
+    Search,
+    /// Reached by graph expansion
+    Traversal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank_by(value: serde_json::Value) -> Result<RankBy, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
+    #[test]
+    fn formula_form_reads_into_weights() {
+        let parsed = rank_by(serde_json::json!({ "formula": "0.7 * vector_similarity + importance*0.2 + recency" })).unwrap();
+        let expected = HashMap::from([
+            (RankFactor::VectorSimilarity, 0.7),
+            (RankFactor::Importance, 0.2),
+            (RankFactor::Recency, 1.0),
+        ]);
+        assert_eq!(parsed.weights, expected);
+        let weights = rank_by(serde_json::json!({ "weights": { "vector_similarity": 0.7 } })).unwrap();
+        assert_eq!(weights.weights, HashMap::from([(RankFactor::VectorSimilarity, 0.7)]));
+
+        assert!(rank_by(serde_json::json!({ "formula": "0.5 * novelty" })).is_err());
+        assert!(rank_by(serde_json::json!({ "formula": "importance * recency" })).is_err());
+        assert!(rank_by(serde_json::json!({ "formula": "" })).is_err());
+    }
 }
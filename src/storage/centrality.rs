@@ -0,0 +1,75 @@
+//! Per-memory graph centrality, computed by `graph::centrality` and stored
+//! next to the memories it describes.
+
+use super::{GRAPH_REVISION, META, StorageManager, StorageReader};
+use crate::graph::centrality::Centrality;
+use anyhow::Result;
+use redb::{ReadableTable, TableDefinition, WriteTransaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// memory id -> `Centrality`
+pub(super) const CENTRALITY: TableDefinition<u128, Vec<u8>> = TableDefinition::new("centrality");
+
+/// `graph_revision` the stored centrality was computed at.
+const CENTRALITY_REVISION: &str = "centrality_revision";
+
+/// Drops the stored centrality of a deleted memory.
+pub(super) fn remove(write_txn: &WriteTransaction, id: Uuid) -> Result<()> {
+    write_txn.open_table(CENTRALITY)?.remove(id.as_u128())?;
+    Ok(())
+}
+
+impl StorageManager {
+    /// Replaces all stored centrality with `scores`, computed at graph
+    /// revision `revision`.
+    ///
+    /// Fails if the graph has changed since `revision`, so a slow computation
+    /// never overwrites the result of a newer one with stale values.
+    pub fn save_centrality(&self, scores: &HashMap<Uuid, Centrality>, revision: u64) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut meta = write_txn.open_table(META)?;
+            let current = meta.get(GRAPH_REVISION)?.map(|v| v.value()).unwrap_or(0);
+            if current != revision {
+                anyhow::bail!("graph changed while centrality was computed (revision {revision}, now {current})");
+            }
+            meta.insert(CENTRALITY_REVISION, revision)?;
+        }
+        {
+            let mut table = write_txn.open_table(CENTRALITY)?;
+            table.retain(|_, _| false)?;
+            for (id, centrality) in scores {
+                table.insert(id.as_u128(), serde_json::to_vec(centrality)?)?;
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Graph revision the stored centrality was computed at, if any.
+    pub fn centrality_revision(&self) -> Result<Option<u64>> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(META)?;
+        Ok(table.get(CENTRALITY_REVISION)?.map(|v| v.value()))
+    }
+}
+
+impl StorageReader {
+    pub fn get_centrality(&self, id: Uuid) -> Result<Option<Centrality>> {
+        self.centrality.get(id.as_u128())?
+            .map(|value| serde_json::from_slice(&value.value()))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Every stored centrality, by memory id.
+    pub fn list_centrality(&self) -> Result<HashMap<Uuid, Centrality>> {
+        let mut scores = HashMap::new();
+        for result in self.centrality.iter()? {
+            let (key, value) = result?;
+            scores.insert(Uuid::from_u128(key.value()), serde_json::from_slice(&value.value())?);
+        }
+        Ok(scores)
+    }
+}
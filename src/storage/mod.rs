@@ -1,17 +1,46 @@
-use crate::models::{Memory, Edge, InboundEdge};
-use crate::index::GraphIndex;
+use crate::models::{DEFAULT_SLOT, EmbeddingModel, Memory, Edge, InboundEdge};
+use crate::index::{GraphIndex, IndexError, VectorSource, validate_vector};
 use anyhow::Result;
-use redb::{Database, ReadableTable, TableDefinition};
-use std::path::Path;
+use redb::{Database, ReadOnlyTable, ReadableTable, ReadableTableMetadata, TableDefinition};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use uuid::Uuid;
 use chrono::Utc;
 
 const MEMORY_TABLE: TableDefinition<u128, Vec<u8>> = TableDefinition::new("memories");
 const EDGES_OUT: TableDefinition<u128, Vec<u8>> = TableDefinition::new("edges_out");
 const EDGES_IN: TableDefinition<u128, Vec<u8>> = TableDefinition::new("edges_in");
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+/// slot -> the `EmbeddingModel` every vector in that slot comes from
+const EMBEDDING_MODELS: TableDefinition<&str, Vec<u8>> = TableDefinition::new("embedding_models");
+
+mod centrality;
+mod text;
+
+/// Bumped on every write to `memories`; derived indexes compare against it.
+const MEMORIES_REVISION: &str = "memories_revision";
+/// Bumped whenever a memory is added or deleted or an edge is added; graph
+/// analytics stored in the database record the revision they were computed at.
+const GRAPH_REVISION: &str = "graph_revision";
+/// Embedding length of the store, fixed by the first saved memory. Named
+/// slots other than `DEFAULT_SLOT` use `embedding_dimension:<slot>`.
+const EMBEDDING_DIMENSION: &str = "embedding_dimension";
+
+fn dimension_key(slot: &str) -> String {
+    if slot == DEFAULT_SLOT {
+        EMBEDDING_DIMENSION.to_string()
+    } else {
+        format!("{EMBEDDING_DIMENSION}:{slot}")
+    }
+}
 
 pub struct StorageManager {
     db: Database,
+    path: PathBuf,
+    /// Copy of the `graph_revision` row as of the last commit. redb locks the
+    /// file to one process, so every write that bumps it goes through here.
+    graph_revision: AtomicU64,
 }
 
 impl GraphIndex for StorageManager {
@@ -28,58 +57,455 @@ impl GraphIndex for StorageManager {
         let edges = self.get_outbound_edges(node)?;
         Ok(edges.into_iter().map(|e| (e.target_id, e.weight)).collect())
     }
+
+    fn get_inbound_neighbors(&self, node: Uuid) -> Result<Vec<(Uuid, f32)>> {
+        let edges = self.get_inbound_edges(node)?;
+        Ok(edges.into_iter().map(|e| (e.source_id, e.weight)).collect())
+    }
+}
+
+impl VectorSource for StorageManager {
+    fn get_vector(&self, id: Uuid, slot: &str) -> Result<Option<Vec<f32>>> {
+        Ok(self.get_memory(id)?.and_then(|memory| memory.vector(slot).map(<[f32]>::to_vec)))
+    }
 }
 
 impl StorageManager {
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
-        let db = Database::create(path)?;
-        Ok(Self { db })
+        let path = path.as_ref().to_path_buf();
+        let db = Database::create(&path)?;
+
+        // Create every table up front so read transactions on a fresh file succeed.
+        let write_txn = db.begin_write()?;
+        let legacy_text_index = text::drop_legacy_postings(&write_txn)?;
+        {
+            write_txn.open_table(MEMORY_TABLE)?;
+            write_txn.open_table(EDGES_OUT)?;
+            write_txn.open_table(EDGES_IN)?;
+            write_txn.open_table(META)?;
+            write_txn.open_table(EMBEDDING_MODELS)?;
+            write_txn.open_table(text::TEXT_POSTINGS)?;
+            write_txn.open_table(text::TEXT_DOCS)?;
+            write_txn.open_table(centrality::CENTRALITY)?;
+        }
+        write_txn.commit()?;
+
+        let graph_revision = {
+            let read_txn = db.begin_read()?;
+            let meta = read_txn.open_table(META)?;
+            meta.get(GRAPH_REVISION)?.map(|v| v.value()).unwrap_or(0)
+        };
+        let storage = Self { db, path, graph_revision: AtomicU64::new(graph_revision) };
+        // Stores written before full-text search existed have no postings yet,
+        // and stores from before composite posting keys need re-indexing.
+        if legacy_text_index || storage.text_index_len()? != storage.memory_count()? {
+            storage.rebuild_text_index()?;
+        }
+        Ok(storage)
     }
 
+    /// Location of the redb file backing this store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Inserts or replaces a memory.
+    ///
+    /// Fails with an `IndexError` if any embedding slot is empty, contains NaN
+    /// or infinite values, does not match that slot's dimension in the store,
+    /// or comes from a different model than the one the slot is pinned to.
+    /// The first tracked model saved into a slot pins it, unless other
+    /// memories already hold untracked vectors in that slot: those fail with
+    /// `IndexError::UntrackedVectors` until `adopt_slot_model` or
+    /// `index::embed::reembed` settles which model they come from.
     pub fn save_memory(&self, memory: &Memory) -> Result<()> {
+        if memory.embeddings.contains_key(DEFAULT_SLOT) || memory.embedding_models.contains_key(DEFAULT_SLOT) {
+            anyhow::bail!("slot `{DEFAULT_SLOT}` is reserved for `Memory::embedding`");
+        }
         let write_txn = self.db.begin_write()?;
         {
+            let mut meta = write_txn.open_table(META)?;
+            let mut models = write_txn.open_table(EMBEDDING_MODELS)?;
+            for (slot, vector) in memory.vectors() {
+                let key = dimension_key(slot);
+                let expected = meta.get(key.as_str())?.map(|v| v.value() as usize);
+                validate_vector(vector, expected)?;
+                if expected.is_none() {
+                    meta.insert(key.as_str(), vector.len() as u64)?;
+                }
+
+                let pinned: Option<EmbeddingModel> = models.get(slot)?
+                    .map(|value| serde_json::from_slice(&value.value()))
+                    .transpose()?;
+                match (pinned, memory.model(slot)) {
+                    (Some(expected), actual) if actual != Some(&expected) => {
+                        return Err(IndexError::ModelMismatch {
+                            slot: slot.to_string(),
+                            expected,
+                            actual: actual.cloned(),
+                        }.into());
+                    }
+                    (None, Some(model)) => {
+                        if expected.is_some() && Self::slot_has_vectors(&write_txn, slot, memory.id)? {
+                            return Err(IndexError::UntrackedVectors {
+                                slot: slot.to_string(),
+                                model: model.clone(),
+                            }.into());
+                        }
+                        models.insert(slot, serde_json::to_vec(model)?)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let added = {
             let mut table = write_txn.open_table(MEMORY_TABLE)?;
             let key = memory.id.as_u128();
             let value = serde_json::to_vec(memory)?;
-            table.insert(key, value)?;
+            table.insert(key, value)?.is_none()
+        };
+        text::index_memory(&write_txn, memory)?;
+        Self::bump_revision(&write_txn)?;
+        let graph_revision = if added { Some(Self::bump_graph_revision(&write_txn)?) } else { None };
+        write_txn.commit()?;
+        self.publish_graph_revision(graph_revision);
+        Ok(())
+    }
+
+    /// Removes a memory together with every edge that starts or ends at it.
+    ///
+    /// Returns `false` if no memory with `id` was stored. Callers holding a
+    /// vector index should `remove` the id from it as well.
+    pub fn delete_memory(&self, id: Uuid) -> Result<bool> {
+        let write_txn = self.db.begin_write()?;
+        let key = id.as_u128();
+        let existed = {
+            let mut table = write_txn.open_table(MEMORY_TABLE)?;
+            table.remove(key)?.is_some()
+        };
+
+        // Drop this memory's own adjacency lists, then its entries in the
+        // opposite index of each neighbour.
+        let outbound: Vec<Edge> = {
+            let mut table = write_txn.open_table(EDGES_OUT)?;
+            match table.remove(key)? {
+                Some(value) => serde_json::from_slice(&value.value())?,
+                None => Vec::new(),
+            }
+        };
+        let inbound: Vec<InboundEdge> = {
+            let mut table = write_txn.open_table(EDGES_IN)?;
+            match table.remove(key)? {
+                Some(value) => serde_json::from_slice(&value.value())?,
+                None => Vec::new(),
+            }
+        };
+
+        {
+            let mut table = write_txn.open_table(EDGES_IN)?;
+            for edge in &outbound {
+                let target = edge.target_id.as_u128();
+                let edges: Option<Vec<InboundEdge>> = table.get(target)?
+                    .map(|value| serde_json::from_slice(&value.value()))
+                    .transpose()?;
+                if let Some(mut edges) = edges {
+                    edges.retain(|e| e.source_id != id);
+                    table.insert(target, serde_json::to_vec(&edges)?)?;
+                }
+            }
+        }
+        {
+            let mut table = write_txn.open_table(EDGES_OUT)?;
+            for edge in &inbound {
+                let source = edge.source_id.as_u128();
+                let edges: Option<Vec<Edge>> = table.get(source)?
+                    .map(|value| serde_json::from_slice(&value.value()))
+                    .transpose()?;
+                if let Some(mut edges) = edges {
+                    edges.retain(|e| e.target_id != id);
+                    table.insert(source, serde_json::to_vec(&edges)?)?;
+                }
+            }
+        }
+
+        if existed {
+            text::unindex_memory(&write_txn, id)?;
+            centrality::remove(&write_txn, id)?;
+            Self::bump_revision(&write_txn)?;
+        }
+        let graph_revision = if existed || !outbound.is_empty() || !inbound.is_empty() {
+            Some(Self::bump_graph_revision(&write_txn)?)
+        } else {
+            None
+        };
+        write_txn.commit()?;
+        self.publish_graph_revision(graph_revision);
+        Ok(existed)
+    }
+
+    /// Monotonic counter of writes to the `memories` table.
+    ///
+    /// A persisted vector index records the revision it was built at, so a
+    /// mismatch on open means the index and the store have diverged.
+    pub fn memories_revision(&self) -> Result<u64> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(META)?;
+        Ok(table.get(MEMORIES_REVISION)?.map(|v| v.value()).unwrap_or(0))
+    }
+
+    /// Monotonic counter of changes to the graph's shape: memories added or
+    /// deleted and edges added. Updates to an existing memory don't count.
+    ///
+    /// Read from memory, so it is cheap enough to check before every lookup.
+    pub fn graph_revision(&self) -> Result<u64> {
+        Ok(self.graph_revision.load(Ordering::Acquire))
+    }
+
+    /// Embedding length every stored memory must have, once the first one is saved.
+    pub fn embedding_dimension(&self) -> Result<Option<usize>> {
+        self.slot_dimension(DEFAULT_SLOT)
+    }
+
+    /// Fixes the store's embedding dimension before any memory is saved.
+    pub fn set_embedding_dimension(&self, dimension: usize) -> Result<()> {
+        self.set_slot_dimension(DEFAULT_SLOT, dimension)
+    }
+
+    /// Dimension of the named embedding slot, once a memory using it is saved.
+    pub fn slot_dimension(&self, slot: &str) -> Result<Option<usize>> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(META)?;
+        Ok(table.get(dimension_key(slot).as_str())?.map(|v| v.value() as usize))
+    }
+
+    pub fn set_slot_dimension(&self, slot: &str, dimension: usize) -> Result<()> {
+        let key = dimension_key(slot);
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut meta = write_txn.open_table(META)?;
+            if let Some(existing) = meta.get(key.as_str())?.map(|v| v.value() as usize)
+                && existing != dimension
+            {
+                return Err(IndexError::DimensionMismatch { expected: existing, actual: dimension }.into());
+            }
+            meta.insert(key.as_str(), dimension as u64)?;
         }
         write_txn.commit()?;
         Ok(())
     }
 
-    pub fn get_memory(&self, id: Uuid) -> Result<Option<Memory>> {
+    /// Embedding model the slot is pinned to, once a tracked memory is saved.
+    pub fn slot_model(&self, slot: &str) -> Result<Option<EmbeddingModel>> {
         let read_txn = self.db.begin_read()?;
-        let table = read_txn.open_table(MEMORY_TABLE)?;
-        let key = id.as_u128();
-        
-        if let Some(value) = table.get(key)? {
+        let table = read_txn.open_table(EMBEDDING_MODELS)?;
+        Ok(table.get(slot)?
+            .map(|value| serde_json::from_slice(&value.value()))
+            .transpose()?)
+    }
+
+    /// Declares that the untracked vectors already in `slot` come from `model`:
+    /// stamps every memory holding the slot with it and pins the slot.
+    ///
+    /// For stores written before models were tracked, when the vectors are
+    /// known to come from `model`; otherwise re-embed with
+    /// `index::embed::reembed`. Fails if the slot is pinned to another model.
+    pub fn adopt_slot_model(&self, slot: &str, model: &EmbeddingModel) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut models = write_txn.open_table(EMBEDDING_MODELS)?;
+            let pinned: Option<EmbeddingModel> = models.get(slot)?
+                .map(|value| serde_json::from_slice(&value.value()))
+                .transpose()?;
+            if let Some(expected) = pinned {
+                if &expected == model {
+                    return Ok(());
+                }
+                return Err(IndexError::ModelMismatch { slot: slot.to_string(), expected, actual: Some(model.clone()) }.into());
+            }
+            models.insert(slot, serde_json::to_vec(model)?)?;
+        }
+        {
+            let mut table = write_txn.open_table(MEMORY_TABLE)?;
+            let mut adopted = Vec::new();
+            for entry in table.iter()? {
+                let (_, value) = entry?;
+                let mut memory: Memory = serde_json::from_slice(&value.value())?;
+                if memory.vector(slot).is_none() || memory.model(slot).is_some() {
+                    continue;
+                }
+                if slot == DEFAULT_SLOT {
+                    memory.embedding_model = Some(model.clone());
+                } else {
+                    memory.embedding_models.insert(slot.to_string(), model.clone());
+                }
+                adopted.push(memory);
+            }
+            for memory in &adopted {
+                table.insert(memory.id.as_u128(), serde_json::to_vec(memory)?)?;
+            }
+        }
+        Self::bump_revision(&write_txn)?;
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Whether any memory other than `except` holds a `slot` vector.
+    fn slot_has_vectors(write_txn: &redb::WriteTransaction, slot: &str, except: Uuid) -> Result<bool> {
+        let table = write_txn.open_table(MEMORY_TABLE)?;
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            if key.value() == except.as_u128() {
+                continue;
+            }
             let memory: Memory = serde_json::from_slice(&value.value())?;
-            Ok(Some(memory))
-        } else {
-            Ok(None)
+            if memory.vector(slot).is_some() {
+                return Ok(true);
+            }
         }
+        Ok(false)
     }
 
-    pub fn list_memories(&self) -> Result<Vec<Memory>> {
+    /// Replaces every embedding in `slot` in one transaction and re-pins the
+    /// slot to `model` and the new vectors' dimension.
+    ///
+    /// `vectors` must cover every memory that has the slot, so the slot never
+    /// mixes models; a memory saved after the vectors were computed fails the
+    /// whole replacement. Used by `index::embed::reembed`.
+    pub fn replace_slot_embeddings(&self, slot: &str, model: &EmbeddingModel, vectors: &HashMap<Uuid, Vec<f32>>) -> Result<()> {
+        let dimension = vectors.values().next().map(Vec::len);
+        for vector in vectors.values() {
+            validate_vector(vector, dimension)?;
+        }
+
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(MEMORY_TABLE)?;
+            let mut updated = Vec::with_capacity(vectors.len());
+            for entry in table.iter()? {
+                let (_, value) = entry?;
+                let mut memory: Memory = serde_json::from_slice(&value.value())?;
+                if memory.vector(slot).is_none() {
+                    continue;
+                }
+                let Some(vector) = vectors.get(&memory.id) else {
+                    anyhow::bail!("memory {} has no re-embedded `{slot}` vector", memory.id);
+                };
+                if slot == DEFAULT_SLOT {
+                    memory.embedding = vector.clone();
+                    memory.embedding_model = Some(model.clone());
+                } else {
+                    memory.embeddings.insert(slot.to_string(), vector.clone());
+                    memory.embedding_models.insert(slot.to_string(), model.clone());
+                }
+                updated.push(memory);
+            }
+            if updated.len() != vectors.len() {
+                anyhow::bail!("{} re-embedded vectors do not belong to a memory with slot `{slot}`", vectors.len() - updated.len());
+            }
+            for memory in &updated {
+                table.insert(memory.id.as_u128(), serde_json::to_vec(memory)?)?;
+            }
+        }
+        {
+            let mut meta = write_txn.open_table(META)?;
+            match dimension {
+                Some(dimension) => meta.insert(dimension_key(slot).as_str(), dimension as u64)?,
+                None => meta.remove(dimension_key(slot).as_str())?,
+            };
+            let mut models = write_txn.open_table(EMBEDDING_MODELS)?;
+            models.insert(slot, serde_json::to_vec(model)?)?;
+        }
+        Self::bump_revision(&write_txn)?;
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    pub fn memory_count(&self) -> Result<u64> {
         let read_txn = self.db.begin_read()?;
         let table = read_txn.open_table(MEMORY_TABLE)?;
-        let mut memories = Vec::new();
-        
-        for result in table.iter()? {
-            let (_, value) = result?;
-            let memory: Memory = serde_json::from_slice(&value.value())?;
-            memories.push(memory);
+        Ok(table.len()?)
+    }
+
+    /// Re-indexes the content of every memory for `StorageReader::search_text`.
+    ///
+    /// Runs automatically on open when the text index is out of step with the
+    /// `memories` table.
+    pub fn rebuild_text_index(&self) -> Result<()> {
+        let memories = self.list_memories()?;
+        let write_txn = self.db.begin_write()?;
+        text::rebuild(&write_txn, &memories)?;
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn text_index_len(&self) -> Result<u64> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(META)?;
+        Ok(table.get(text::TEXT_DOC_COUNT)?.map(|v| v.value()).unwrap_or(0))
+    }
+
+    fn bump_revision(write_txn: &redb::WriteTransaction) -> Result<()> {
+        let mut meta = write_txn.open_table(META)?;
+        let next = meta.get(MEMORIES_REVISION)?.map(|v| v.value()).unwrap_or(0) + 1;
+        meta.insert(MEMORIES_REVISION, next)?;
+        Ok(())
+    }
+
+    /// Returns the new revision, to `publish_graph_revision` once committed.
+    fn bump_graph_revision(write_txn: &redb::WriteTransaction) -> Result<u64> {
+        let mut meta = write_txn.open_table(META)?;
+        let next = meta.get(GRAPH_REVISION)?.map(|v| v.value()).unwrap_or(0) + 1;
+        meta.insert(GRAPH_REVISION, next)?;
+        Ok(next)
+    }
+
+    /// Records a committed `graph_revision`. Writers commit in revision order
+    /// but may publish out of order, so the counter only moves forward.
+    fn publish_graph_revision(&self, revision: Option<u64>) {
+        if let Some(revision) = revision {
+            self.graph_revision.fetch_max(revision, Ordering::AcqRel);
         }
-        
-        Ok(memories)
+    }
+
+    /// Opens a read snapshot for a batch of lookups.
+    ///
+    /// Every lookup through the returned reader sees the same committed state
+    /// and skips the per-call transaction setup of the methods below.
+    pub fn read(&self) -> Result<StorageReader> {
+        let read_txn = self.db.begin_read()?;
+        Ok(StorageReader {
+            memories: read_txn.open_table(MEMORY_TABLE)?,
+            edges_out: read_txn.open_table(EDGES_OUT)?,
+            edges_in: read_txn.open_table(EDGES_IN)?,
+            text_postings: read_txn.open_table(text::TEXT_POSTINGS)?,
+            meta: read_txn.open_table(META)?,
+            centrality: read_txn.open_table(centrality::CENTRALITY)?,
+        })
+    }
+
+    pub fn get_memory(&self, id: Uuid) -> Result<Option<Memory>> {
+        self.read()?.get_memory(id)
+    }
+
+    pub fn list_memories(&self) -> Result<Vec<Memory>> {
+        self.read()?.list_memories()
     }
 
     // --- Graph Operations ---
 
     pub fn add_edge_inherent(&self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<()> {
+        self.insert_edge(source, target, relation_type, weight).map(|_| ())
+    }
+
+    /// `add_edge_inherent`, returning the outbound edge as stored.
+    pub(crate) fn insert_edge(&self, source: Uuid, target: Uuid, relation_type: String, weight: f32) -> Result<Edge> {
         let write_txn = self.db.begin_write()?;
-        let now = Utc::now();
+        let edge = Edge {
+            target_id: target,
+            relation_type,
+            weight,
+            created_at: Utc::now(),
+        };
 
         // 1. Update Outbound Index (Source -> Target)
         {
@@ -92,12 +518,7 @@ impl StorageManager {
                 Vec::new()
             };
 
-            edges.push(Edge {
-                target_id: target,
-                relation_type: relation_type.clone(),
-                weight,
-                created_at: now,
-            });
+            edges.push(edge.clone());
 
             table.insert(key, serde_json::to_vec(&edges)?)?;
         }
@@ -115,24 +536,92 @@ impl StorageManager {
 
             edges.push(InboundEdge {
                 source_id: source,
-                relation_type,
+                relation_type: edge.relation_type.clone(),
                 weight,
-                created_at: now,
+                created_at: edge.created_at,
             });
 
             table.insert(key, serde_json::to_vec(&edges)?)?;
         }
 
+        let graph_revision = Self::bump_graph_revision(&write_txn)?;
         write_txn.commit()?;
-        Ok(())
+        self.publish_graph_revision(Some(graph_revision));
+        Ok(edge)
     }
 
     pub fn get_outbound_edges(&self, id: Uuid) -> Result<Vec<Edge>> {
-        let read_txn = self.db.begin_read()?;
-        let table = read_txn.open_table(EDGES_OUT)?;
+        self.read()?.get_outbound_edges(id)
+    }
+
+    pub fn get_inbound_edges(&self, id: Uuid) -> Result<Vec<InboundEdge>> {
+        self.read()?.get_inbound_edges(id)
+    }
+}
+
+/// Read-only snapshot of the store, see `StorageManager::read`.
+pub struct StorageReader {
+    memories: ReadOnlyTable<u128, Vec<u8>>,
+    edges_out: ReadOnlyTable<u128, Vec<u8>>,
+    edges_in: ReadOnlyTable<u128, Vec<u8>>,
+    text_postings: ReadOnlyTable<(&'static str, u128), (u32, u32)>,
+    meta: ReadOnlyTable<&'static str, u64>,
+    centrality: ReadOnlyTable<u128, Vec<u8>>,
+}
+
+impl StorageReader {
+    pub fn get_memory(&self, id: Uuid) -> Result<Option<Memory>> {
         let key = id.as_u128();
         
-        if let Some(value) = table.get(key)? {
+        if let Some(value) = self.memories.get(key)? {
+            let memory: Memory = serde_json::from_slice(&value.value())?;
+            Ok(Some(memory))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn list_memories(&self) -> Result<Vec<Memory>> {
+        let mut memories = Vec::new();
+        
+        for result in self.memories.iter()? {
+            let (_, value) = result?;
+            let memory: Memory = serde_json::from_slice(&value.value())?;
+            memories.push(memory);
+        }
+        
+        Ok(memories)
+    }
+
+    /// Ids of every stored memory, without decoding them.
+    pub fn memory_ids(&self) -> Result<Vec<Uuid>> {
+        let mut ids = Vec::new();
+        for result in self.memories.iter()? {
+            let (key, _) = result?;
+            ids.push(Uuid::from_u128(key.value()));
+        }
+        Ok(ids)
+    }
+
+    /// Graph revision this snapshot was taken at, see `StorageManager::graph_revision`.
+    pub fn graph_revision(&self) -> Result<u64> {
+        Ok(self.meta.get(GRAPH_REVISION)?.map(|v| v.value()).unwrap_or(0))
+    }
+
+    /// Every adjacency list in `edges_out`, by source memory.
+    pub fn list_outbound_edges(&self) -> Result<Vec<(Uuid, Vec<Edge>)>> {
+        let mut lists = Vec::new();
+        for result in self.edges_out.iter()? {
+            let (key, value) = result?;
+            lists.push((Uuid::from_u128(key.value()), serde_json::from_slice(&value.value())?));
+        }
+        Ok(lists)
+    }
+
+    pub fn get_outbound_edges(&self, id: Uuid) -> Result<Vec<Edge>> {
+        let key = id.as_u128();
+        
+        if let Some(value) = self.edges_out.get(key)? {
             let edges: Vec<Edge> = serde_json::from_slice(&value.value())?;
             Ok(edges)
         } else {
@@ -141,11 +630,9 @@ impl StorageManager {
     }
 
     pub fn get_inbound_edges(&self, id: Uuid) -> Result<Vec<InboundEdge>> {
-        let read_txn = self.db.begin_read()?;
-        let table = read_txn.open_table(EDGES_IN)?;
         let key = id.as_u128();
         
-        if let Some(value) = table.get(key)? {
+        if let Some(value) = self.edges_in.get(key)? {
             let edges: Vec<InboundEdge> = serde_json::from_slice(&value.value())?;
             Ok(edges)
         } else {
@@ -153,3 +640,135 @@ impl StorageManager {
         }
     }
 }
+
+/// A store in its own temporary directory, removed with everything written
+/// beside it (index snapshots, mmap files) when dropped.
+#[cfg(test)]
+pub(crate) struct TempStorage {
+    storage: std::sync::Arc<StorageManager>,
+    dir: PathBuf,
+}
+
+#[cfg(test)]
+impl TempStorage {
+    pub(crate) fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("memory_graph-test-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        let storage = StorageManager::new(dir.join("memories.redb")).expect("open temp store");
+        Self { storage: std::sync::Arc::new(storage), dir }
+    }
+
+    /// Shared handle, for types that hold on to the store.
+    pub(crate) fn shared(&self) -> std::sync::Arc<StorageManager> {
+        self.storage.clone()
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempStorage {
+    type Target = StorageManager;
+
+    fn deref(&self) -> &StorageManager {
+        &self.storage
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempStorage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MemoryType;
+
+    fn memory(embedding: Vec<f32>, model: Option<&EmbeddingModel>) -> Memory {
+        let mut memory = Memory::new(
+            "test memory".to_string(),
+            embedding,
+            MemoryType::Semantic { confidence: 1.0, source: "test".to_string() },
+        );
+        memory.embedding_model = model.cloned();
+        memory
+    }
+
+    #[test]
+    fn first_memory_pins_each_slot_dimension() {
+        let storage = TempStorage::new();
+        let rejected = |memory: &Memory| storage.save_memory(memory).unwrap_err().downcast::<IndexError>().unwrap();
+        assert_eq!(rejected(&memory(vec![], None)), IndexError::EmptyVector);
+        assert_eq!(rejected(&memory(vec![1.0, f32::NAN], None)), IndexError::NonFinite { position: 1 });
+        assert_eq!(storage.slot_dimension(DEFAULT_SLOT).unwrap(), None);
+
+        let mut first = memory(vec![1.0, 0.0, 0.0], None);
+        first.embeddings.insert("summary".to_string(), vec![1.0, 0.0]);
+        storage.save_memory(&first).unwrap();
+        assert_eq!(storage.slot_dimension(DEFAULT_SLOT).unwrap(), Some(3));
+        assert_eq!(storage.slot_dimension("summary").unwrap(), Some(2));
+
+        assert_eq!(rejected(&memory(vec![1.0, 0.0], None)), IndexError::DimensionMismatch { expected: 3, actual: 2 });
+        let mut wrong_slot = memory(vec![0.0, 1.0, 0.0], None);
+        wrong_slot.embeddings.insert("summary".to_string(), vec![1.0, 0.0, 0.0]);
+        assert_eq!(rejected(&wrong_slot), IndexError::DimensionMismatch { expected: 2, actual: 3 });
+        let mut infinite_slot = memory(vec![0.0, 1.0, 0.0], None);
+        infinite_slot.embeddings.insert("summary".to_string(), vec![f32::INFINITY, 0.0]);
+        assert_eq!(rejected(&infinite_slot), IndexError::NonFinite { position: 0 });
+
+        // Rejected saves write nothing, and updates are checked the same way.
+        assert_eq!(storage.memory_count().unwrap(), 1);
+        let mut shrunk = first.clone();
+        shrunk.embedding = vec![1.0];
+        assert_eq!(rejected(&shrunk), IndexError::DimensionMismatch { expected: 3, actual: 1 });
+        assert_eq!(storage.get_memory(first.id).unwrap().unwrap().embedding, first.embedding);
+        storage.save_memory(&memory(vec![0.0, 0.0, 1.0], None)).unwrap();
+        assert_eq!(storage.memory_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn vector_source_reads_named_slots() {
+        let storage = TempStorage::new();
+        let mut memory = memory(vec![1.0, 0.0, 0.0, 0.0], None);
+        memory.embeddings.insert("summary".to_string(), vec![0.0, 1.0]);
+        storage.save_memory(&memory).unwrap();
+        assert_eq!(storage.get_vector(memory.id, "summary").unwrap(), Some(vec![0.0, 1.0]));
+        assert_eq!(storage.get_vector(memory.id, DEFAULT_SLOT).unwrap(), Some(memory.embedding.clone()));
+        assert_eq!(storage.get_vector(memory.id, "emotion").unwrap(), None);
+        assert_eq!(storage.get_vector(Uuid::now_v7(), DEFAULT_SLOT).unwrap(), None);
+    }
+
+    #[test]
+    fn untracked_vectors_block_implicit_pinning() {
+        let storage = TempStorage::new();
+        let model = EmbeddingModel::new("minilm", "v2");
+        let untracked = memory(vec![1.0, 0.0], None);
+        storage.save_memory(&untracked).unwrap();
+
+        let err = storage.save_memory(&memory(vec![0.0, 1.0], Some(&model))).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(IndexError::UntrackedVectors { .. })), "{err}");
+        assert_eq!(storage.slot_model(DEFAULT_SLOT).unwrap(), None);
+        // Re-saving the only untracked memory with a model is not mixing.
+        let mut resaved = untracked.clone();
+        resaved.embedding_model = Some(model.clone());
+        storage.save_memory(&resaved).unwrap();
+        assert_eq!(storage.slot_model(DEFAULT_SLOT).unwrap(), Some(model));
+    }
+
+    #[test]
+    fn adopting_a_model_stamps_untracked_vectors() {
+        let storage = TempStorage::new();
+        let model = EmbeddingModel::new("minilm", "v2");
+        let untracked = memory(vec![1.0, 0.0], None);
+        storage.save_memory(&untracked).unwrap();
+
+        storage.adopt_slot_model(DEFAULT_SLOT, &model).unwrap();
+        assert_eq!(storage.slot_model(DEFAULT_SLOT).unwrap(), Some(model.clone()));
+        assert_eq!(storage.get_memory(untracked.id).unwrap().unwrap().embedding_model, Some(model.clone()));
+        storage.save_memory(&memory(vec![0.0, 1.0], Some(&model))).unwrap();
+
+        let other = EmbeddingModel::new("e5", "large");
+        assert!(storage.adopt_slot_model(DEFAULT_SLOT, &other).is_err());
+    }
+}
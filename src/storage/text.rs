@@ -0,0 +1,191 @@
+//! Inverted index over `Memory.content`, kept in the same redb file.
+//!
+//! Postings are updated inside the write transaction that saves or deletes the
+//! memory, so lexical search always sees exactly the committed memories.
+//! Each posting is its own `(term, memory id)` row, so indexing a memory only
+//! writes its own rows however common its terms are.
+
+use super::{META, StorageReader};
+use crate::index::text::{Bm25, term_frequencies, tokenize};
+use crate::index::topk::TopK;
+use crate::models::Memory;
+use anyhow::Result;
+use redb::{ReadableTable, TableDefinition, TableHandle, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// (term, memory id) -> (term frequency, document length)
+pub(super) const TEXT_POSTINGS: TableDefinition<(&str, u128), (u32, u32)> = TableDefinition::new("text_term_postings");
+/// Earlier layout holding each term's whole posting list as one JSON value.
+const LEGACY_TEXT_POSTINGS: &str = "text_postings";
+/// memory id -> the terms it was indexed under, needed to unindex it
+pub(super) const TEXT_DOCS: TableDefinition<u128, Vec<u8>> = TableDefinition::new("text_docs");
+
+pub(super) const TEXT_DOC_COUNT: &str = "text_doc_count";
+const TEXT_TOTAL_LEN: &str = "text_total_len";
+
+#[derive(Serialize, Deserialize)]
+struct TextDoc {
+    len: u32,
+    terms: Vec<String>,
+}
+
+/// Drops the pre-composite-key postings table, returning whether it existed
+/// so the caller can re-index into the current layout.
+pub(super) fn drop_legacy_postings(write_txn: &WriteTransaction) -> Result<bool> {
+    let legacy = write_txn.list_tables()?.any(|table| table.name() == LEGACY_TEXT_POSTINGS);
+    if legacy {
+        write_txn.delete_table(TableDefinition::<&str, Vec<u8>>::new(LEGACY_TEXT_POSTINGS))?;
+    }
+    Ok(legacy)
+}
+
+fn adjust_meta(write_txn: &WriteTransaction, docs: i64, len: i64) -> Result<()> {
+    let mut meta = write_txn.open_table(META)?;
+    for (key, delta) in [(TEXT_DOC_COUNT, docs), (TEXT_TOTAL_LEN, len)] {
+        let current = meta.get(key)?.map(|v| v.value()).unwrap_or(0) as i64;
+        meta.insert(key, (current + delta).max(0) as u64)?;
+    }
+    Ok(())
+}
+
+/// Indexes `memory.content`, replacing any earlier entry for the same id.
+pub(super) fn index_memory(write_txn: &WriteTransaction, memory: &Memory) -> Result<()> {
+    unindex_memory(write_txn, memory.id)?;
+
+    let (counts, len) = term_frequencies(&memory.content);
+    {
+        let mut postings = write_txn.open_table(TEXT_POSTINGS)?;
+        for (term, term_freq) in &counts {
+            postings.insert((term.as_str(), memory.id.as_u128()), (*term_freq, len))?;
+        }
+    }
+    {
+        let mut docs = write_txn.open_table(TEXT_DOCS)?;
+        let doc = TextDoc { len, terms: counts.into_keys().collect() };
+        docs.insert(memory.id.as_u128(), serde_json::to_vec(&doc)?)?;
+    }
+    adjust_meta(write_txn, 1, len as i64)
+}
+
+/// Drops `id`'s posting under every term it was indexed with.
+pub(super) fn unindex_memory(write_txn: &WriteTransaction, id: Uuid) -> Result<()> {
+    let doc: TextDoc = {
+        let mut docs = write_txn.open_table(TEXT_DOCS)?;
+        match docs.remove(id.as_u128())? {
+            Some(value) => serde_json::from_slice(&value.value())?,
+            None => return Ok(()),
+        }
+    };
+
+    {
+        let mut postings = write_txn.open_table(TEXT_POSTINGS)?;
+        for term in &doc.terms {
+            postings.remove((term.as_str(), id.as_u128()))?;
+        }
+    }
+    adjust_meta(write_txn, -1, -(doc.len as i64))
+}
+
+/// Clears the text tables and re-indexes every memory in `memories`.
+pub(super) fn rebuild(write_txn: &WriteTransaction, memories: &[Memory]) -> Result<()> {
+    write_txn.delete_table(TEXT_POSTINGS)?;
+    write_txn.delete_table(TEXT_DOCS)?;
+    {
+        let mut meta = write_txn.open_table(META)?;
+        meta.insert(TEXT_DOC_COUNT, 0)?;
+        meta.insert(TEXT_TOTAL_LEN, 0)?;
+    }
+    write_txn.open_table(TEXT_POSTINGS)?;
+    write_txn.open_table(TEXT_DOCS)?;
+    for memory in memories {
+        index_memory(write_txn, memory)?;
+    }
+    Ok(())
+}
+
+impl StorageReader {
+    /// BM25-ranked memories whose content matches `query`, best first.
+    ///
+    /// Only ids accepted by `allowed` are scored, mirroring
+    /// `VectorIndex::search_filtered`.
+    pub fn search_text(&self, query: &str, k: usize, allowed: &dyn Fn(Uuid) -> bool) -> Result<Vec<(Uuid, f32)>> {
+        let bm25 = Bm25::default();
+        let docs = self.meta.get(TEXT_DOC_COUNT)?.map(|v| v.value()).unwrap_or(0);
+        if docs == 0 {
+            return Ok(Vec::new());
+        }
+        let total_len = self.meta.get(TEXT_TOTAL_LEN)?.map(|v| v.value()).unwrap_or(0);
+        let avg_doc_len = total_len as f32 / docs as f32;
+
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        let mut scores: HashMap<Uuid, f32> = HashMap::new();
+        for term in &terms {
+            let mut postings = Vec::new();
+            for row in self.text_postings.range((term.as_str(), 0)..=(term.as_str(), u128::MAX))? {
+                let (key, value) = row?;
+                let (term_freq, doc_len) = value.value();
+                postings.push((Uuid::from_u128(key.value().1), term_freq, doc_len));
+            }
+            let idf = bm25.idf(docs, postings.len() as u64);
+            for (id, term_freq, doc_len) in postings {
+                if allowed(id) {
+                    *scores.entry(id).or_insert(0.0) += bm25.term_score(idf, term_freq, doc_len, avg_doc_len);
+                }
+            }
+        }
+
+        let mut top = TopK::new(k);
+        top.extend(scores);
+        Ok(top.into_sorted_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Memory, MemoryType};
+    use crate::storage::TempStorage;
+
+    fn note(content: &str) -> Memory {
+        Memory::new(content.to_string(), vec![1.0], MemoryType::Semantic { confidence: 1.0, source: "test".to_string() })
+    }
+
+    #[test]
+    fn bm25_ranks_rare_terms_and_tracks_writes() {
+        let storage = TempStorage::new();
+        let mut notes: Vec<Memory> = [
+            "the oxygen scrubber in the habitat failed",
+            "oxygen levels in the greenhouse are nominal",
+            "oxygen reserve check oxygen oxygen",
+            "Commander Lewis repaired the scrubber",
+        ].into_iter().map(note).collect();
+        for memory in &notes {
+            storage.save_memory(memory).unwrap();
+        }
+        let search = |query: &str| {
+            storage.read().unwrap().search_text(query, 10, &|_| true).unwrap()
+                .into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+        };
+
+        // "scrubber" is rarer than "oxygen", so both scrubber notes lead and
+        // the one matching both terms ranks first.
+        let hits = search("oxygen scrubber");
+        assert_eq!(hits.len(), 4);
+        assert_eq!(hits[0], notes[0].id);
+        assert_eq!(hits[1], notes[3].id);
+        // Repeating a term helps, with saturation.
+        assert_eq!(search("oxygen")[0], notes[2].id);
+        // Stopwords are not indexed.
+        assert!(search("the in").is_empty());
+
+        notes[3].content = "Commander Lewis inspected the airlock".to_string();
+        storage.save_memory(&notes[3]).unwrap();
+        assert_eq!(search("scrubber"), vec![notes[0].id]);
+        assert_eq!(search("airlock"), vec![notes[3].id]);
+
+        storage.delete_memory(notes[0].id).unwrap();
+        assert!(search("scrubber").is_empty());
+        assert_eq!(search("oxygen").len(), 2);
+    }
+}